@@ -1,4 +1,10 @@
 function! LSP#filename() abort
+    " Virtual documents are named by their URI, which is not a path.
+    let l:bufname = empty(expand('<afile>')) ? expand('%') : expand('<afile>')
+    if l:bufname =~# '^\a[[:alnum:]+.-]\+:/' && l:bufname !~# '^file:'
+        return l:bufname
+    endif
+
     " When executing autocommand, `%` might have already changed.
     let l:filename = expand('<afile>:p')
     if !l:filename
@@ -10,11 +16,19 @@ endfunction
 function! LSP#text(...) abort
     let l:buf = get(a:000, 0, '')
 
-    let l:lines = getbufline(l:buf, 1, '$')
-    if len(l:lines) > 0 && l:lines[-1] !=# '' && &fixendofline
-        let l:lines += ['']
-    endif
-    return l:lines
+    return getbufline(l:buf, 1, '$')
+endfunction
+
+" How the buffer is written to disk. Together with LSP#text(), this gives the file content.
+function! LSP#document_format(...) abort
+    let l:buf = get(a:000, 0, '')
+
+    return {
+                \ 'fileformat': getbufvar(l:buf, '&fileformat'),
+                \ 'fileencoding': getbufvar(l:buf, '&fileencoding'),
+                \ 'endofline': getbufvar(l:buf, '&endofline') ? v:true : v:false,
+                \ 'fixendofline': getbufvar(l:buf, '&fixendofline') ? v:true : v:false,
+                \ }
 endfunction
 
 function! LSP#line() abort
@@ -53,6 +67,27 @@ function! LSP#viewport() abort
         \ }
 endfunction
 
+" Path of file as keyed by the language client, which canonicalizes paths.
+function! LSP#canonicalize(filename) abort
+    return resolve(fnamemodify(a:filename, ':p'))
+endfunction
+
+" Windows displaying buffer of file, with their visible lines.
+function! LSP#windows(filename) abort
+    let l:windows = []
+    for l:info in getwininfo()
+        if LSP#canonicalize(bufname(l:info.bufnr)) !=# LSP#canonicalize(a:filename)
+            continue
+        endif
+        call add(l:windows, {
+                    \ 'bufnr': l:info.bufnr,
+                    \ 'viewport': {'start': l:info.topline - 1, 'end': l:info.botline},
+                    \ 'current': l:info.winid == win_getid() ? v:true : v:false,
+                    \ })
+    endfor
+    return l:windows
+endfunction
+
 function! LSP#position() abort
 	return {
 		\ 'line': LSP#line(),
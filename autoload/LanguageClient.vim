@@ -215,6 +215,42 @@ function! s:Edit(action, path) abort
     execute l:action . ' ' . fnameescape(a:path)
 endfunction
 
+" Show a location in the preview window, leaving the cursor in current window.
+function! s:Peek(path, line, col) abort
+    let l:winid = win_getid()
+    execute 'silent! pedit' fnameescape(a:path)
+    silent! wincmd P
+    if !&previewwindow
+        return
+    endif
+    call cursor(a:line, a:col)
+    setlocal cursorline
+    normal! zz
+    call win_gotoid(l:winid)
+endfunction
+
+" Show the call hierarchy in its own buffer, keeping the view when updated.
+function! s:ShowCallHierarchy(lines) abort
+    let l:bufname = '__LanguageClient_CallHierarchy__'
+    let l:winnr = bufwinnr('^' . l:bufname . '$')
+    if l:winnr == -1
+        execute 'silent! botright' &previewheight 'split' l:bufname
+        setlocal buftype=nofile bufhidden=hide noswapfile nobuflisted nowrap
+        nnoremap <buffer> <silent> <CR> :call LanguageClient#callHierarchyJump()<CR>
+        nnoremap <buffer> <silent> o :call LanguageClient#callHierarchyToggle()<CR>
+        nnoremap <buffer> <silent> q :close<CR>
+    else
+        execute l:winnr . 'wincmd w'
+    endif
+
+    let l:view = winsaveview()
+    setlocal modifiable
+    silent %delete _
+    call setline(1, a:lines)
+    setlocal nomodifiable
+    call winrestview(l:view)
+endfunction
+
 " Batch version of `matchdelete()`.
 function! s:MatchDelete(ids) abort
     for l:id in a:ids
@@ -222,10 +258,25 @@ function! s:MatchDelete(ids) abort
     endfor
 endfunction
 
+" Update in place the quickfix or location list with given title, or else add it
+" at the end of the stack. Other lists, and which one is current, are kept.
+function! s:UpdateList(items, title, loclist) abort
+    let l:Get = a:loclist ? function('getloclist', [0]) : function('getqflist')
+    let l:Set = a:loclist ? function('setloclist', [0]) : function('setqflist')
+    for l:nr in range(1, l:Get({'nr': '$'}).nr)
+        let l:list = l:Get({'nr': l:nr, 'id': 0, 'title': 0})
+        if l:list.title ==# a:title
+            return l:Set([], 'r', {'id': l:list.id, 'items': a:items, 'title': a:title})
+        endif
+    endfor
+    return l:Set([], ' ', {'nr': '$', 'items': a:items, 'title': a:title})
+endfunction
+
 " Batch version of nvim_buf_add_highlight
-function! s:AddHighlights(source, highlights) abort
+function! s:AddHighlights(source, highlights, ...) abort
+    let l:bufnr = get(a:000, 0, 0)
     for hl in a:highlights
-        call nvim_buf_add_highlight(0, a:source, hl.group, hl.line, hl.character_start, hl.character_end)
+        call nvim_buf_add_highlight(l:bufnr, a:source, hl.group, hl.line, hl.character_start, hl.character_end)
     endfor
 endfunction
 
@@ -460,8 +511,17 @@ function! LanguageClient#Write(message) abort
     endif
 endfunction
 
+" Whether current buffer is not one language servers know about. Virtual documents are scratch
+" buffers, but still backed by the server.
+function! s:SkipBuffer() abort
+    let l:is_scratch = &buftype !=# '' && !get(b:, 'LanguageClient_virtualDocument', 0)
+    return l:is_scratch || &filetype ==# '' || expand('%') ==# ''
+endfunction
+
+" Optional arguments: whether to skip adding buffer params, and whether to send the message from any
+" buffer, e.g., from one of ours.
 function! LanguageClient#Call(method, params, callback, ...) abort
-    if &buftype !=# '' || &filetype ==# '' || expand('%') ==# ''
+    if !get(a:000, 1, v:false) && s:SkipBuffer()
         " call s:Debug('Skip sending message')
         return
     endif
@@ -492,7 +552,7 @@ function! LanguageClient#Call(method, params, callback, ...) abort
 endfunction
 
 function! LanguageClient#Notify(method, params) abort
-    if &buftype !=# '' || &filetype ==# '' || expand('%') ==# ''
+    if s:SkipBuffer()
         " call s:Debug('Skip sending message')
         return
     endif
@@ -533,12 +593,21 @@ function! LanguageClient#findLocations(...) abort
                 \ 'line': LSP#line(),
                 \ 'character': LSP#character(),
                 \ 'gotoCmd': v:null,
+                \ 'peek': 0,
                 \ 'handle': s:IsFalse(l:Callback),
                 \ }
     call extend(l:params, get(a:000, 0, {}))
     return LanguageClient#Call('languageClient/findLocations', l:params, l:Callback)
 endfunction
 
+function! LanguageClient#textDocument_declaration(...) abort
+    let l:params = {
+                \ 'method': 'textDocument/declaration',
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return call('LanguageClient#findLocations', [l:params] + a:000[1:])
+endfunction
+
 function! LanguageClient#textDocument_definition(...) abort
     let l:params = {
                 \ 'method': 'textDocument/definition',
@@ -578,6 +647,37 @@ function! LanguageClient#textDocument_references(...) abort
     return LanguageClient#Call('textDocument/references', l:params, l:Callback)
 endfunction
 
+function! s:CallHierarchy(direction, options) abort
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'text': LSP#text(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ 'direction': a:direction,
+                \ 'display': 'tree',
+                \ }
+    call extend(l:params, a:options)
+    return LanguageClient#Call('languageClient/callHierarchy', l:params, v:null)
+endfunction
+
+function! LanguageClient#callHierarchy_incomingCalls(...) abort
+    return s:CallHierarchy('incoming', get(a:000, 0, {}))
+endfunction
+
+function! LanguageClient#callHierarchy_outgoingCalls(...) abort
+    return s:CallHierarchy('outgoing', get(a:000, 0, {}))
+endfunction
+
+" Expand or collapse the call hierarchy item under cursor.
+function! LanguageClient#callHierarchyToggle() abort
+    return LanguageClient#Call('languageClient/callHierarchyToggle', {'line': LSP#line()}, v:null, v:false, v:true)
+endfunction
+
+" Go to the call site of the call hierarchy item under cursor.
+function! LanguageClient#callHierarchyJump() abort
+    return LanguageClient#Call('languageClient/callHierarchyJump', {'line': LSP#line()}, v:null, v:false, v:true)
+endfunction
+
 function! LanguageClient#textDocument_rename(...) abort
     let l:params = {
                 \ 'filename': LSP#filename(),
@@ -831,6 +931,21 @@ function! LanguageClient#handleTextChanged() abort
     endtry
 endfunction
 
+function! LanguageClient#handleBufWritePre() abort
+    if !get(b:, 'LanguageClient_handleBufWritePre', 0)
+        return
+    endif
+
+    try
+        " Synchronous, so that edits from the server are applied before the buffer is written.
+        call LanguageClient_runSync('LanguageClient#Call', 'languageClient/handleBufWritePre', {
+                    \ 'filename': LSP#filename(),
+                    \ })
+    catch
+        call s:Debug('LanguageClient caught exception: ' . string(v:exception))
+    endtry
+endfunction
+
 function! LanguageClient#handleBufWritePost() abort
     try
         call LanguageClient#Notify('languageClient/handleBufWritePost', {
@@ -851,6 +966,16 @@ function! LanguageClient#handleBufDelete() abort
     endtry
 endfunction
 
+function! LanguageClient#handleBufEnter() abort
+    try
+        call LanguageClient#Notify('languageClient/handleBufEnter', {
+                    \ 'filename': LSP#filename(),
+                    \ })
+    catch
+        call s:Debug('LanguageClient caught exception: ' . string(v:exception))
+    endtry
+endfunction
+
 let s:last_cursor_line = -1
 function! LanguageClient#handleCursorMoved() abort
     let l:cursor_line = getcurpos()[1] - 1
@@ -936,6 +1061,56 @@ function! LanguageClient#explainErrorAtPoint(...) abort
     return LanguageClient#Call('languageClient/explainErrorAtPoint', l:params, l:Callback)
 endfunction
 
+function! LanguageClient#exportDiagnostics(format, path, ...) abort
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'format': a:format,
+                \ 'path': fnamemodify(expand(a:path), ':p'),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    let l:params.workspace = !!get(l:params, 'workspace', 0)
+    return LanguageClient#Call('languageClient/exportDiagnostics', l:params, v:null)
+endfunction
+
+function! LanguageClient#writeDiagnosticsBaseline(...) abort
+    let l:params = get(a:000, 0, {})
+    return LanguageClient#Call('languageClient/writeDiagnosticsBaseline', l:params, v:null)
+endfunction
+
+function! s:GotoDiagnostic(direction, options) abort
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ 'direction': a:direction,
+                \ 'severity': v:null,
+                \ }
+    call extend(l:params, a:options)
+    let l:params.wrap = !!get(l:params, 'wrap', 0)
+    return LanguageClient#Call('languageClient/gotoDiagnostic', l:params, v:null)
+endfunction
+
+function! LanguageClient#diagnosticsNext(...) abort
+    return s:GotoDiagnostic('next', get(a:000, 0, {}))
+endfunction
+
+function! LanguageClient#diagnosticsPrevious(...) abort
+    return s:GotoDiagnostic('previous', get(a:000, 0, {}))
+endfunction
+
+function! LanguageClient#gotoRelatedInformation(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'buftype': &buftype,
+                \ 'filename': LSP#filename(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ 'handle': s:IsFalse(l:Callback),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/gotoRelatedInformation', l:params, l:Callback)
+endfunction
+
 let g:LanguageClient_omniCompleteResults = []
 function! LanguageClient#omniComplete(...) abort
     try
@@ -1041,6 +1216,29 @@ function! LanguageClient#serverStatusMessage() abort
     return g:LanguageClient_serverStatusMessage
 endfunction
 
+" State of servers and diagnostic counts, kept up to date by the language client.
+let g:LanguageClient_status = {'servers': {}, 'diagnostics': {}}
+
+function! s:SetStatus(status) abort
+    let g:LanguageClient_status = a:status
+endfunction
+
+" Status of a buffer, cheap enough to be called from status line.
+function! LanguageClient#statusInfo(...) abort
+    let l:bufnr = get(a:000, 0, bufnr(''))
+    let l:filetype = getbufvar(l:bufnr, '&filetype')
+    let l:filename = LSP#canonicalize(bufname(l:bufnr))
+    let l:counts = {'error': 0, 'warning': 0, 'information': 0, 'hint': 0}
+    let l:server = get(g:LanguageClient_status.servers, l:filetype, {})
+    return {
+                \ 'state': get(l:server, 'state', 'stopped'),
+                \ 'root': get(l:server, 'root', ''),
+                \ 'progress': get(l:server, 'progress', []),
+                \ 'diagnostics': get(g:LanguageClient_status.diagnostics, l:filename, l:counts),
+                \ 'workspaceDiagnostics': get(l:server, 'diagnostics', l:counts),
+                \ }
+endfunction
+
 " Example function usable for status line.
 function! LanguageClient#statusLine() abort
     if g:LanguageClient_serverStatusMessage ==# ''
@@ -1083,11 +1281,14 @@ endfunction
 function! LanguageClient_contextMenuItems() abort
     return {
                 \ 'Code Action': 'LanguageClient#textDocument_codeAction',
+                \ 'Declaration': 'LanguageClient#textDocument_declaration',
                 \ 'Definition': 'LanguageClient#textDocument_definition',
                 \ 'Document Symbol': 'LanguageClient#textDocument_documentSymbol',
                 \ 'Formatting': 'LanguageClient#textDocument_formatting',
                 \ 'Hover': 'LanguageClient#textDocument_hover',
                 \ 'Implementation': 'LanguageClient#textDocument_implementation',
+                \ 'Incoming Calls': 'LanguageClient#callHierarchy_incomingCalls',
+                \ 'Outgoing Calls': 'LanguageClient#callHierarchy_outgoingCalls',
                 \ 'Range Formatting': 'LanguageClient#textDocument_rangeFormatting',
                 \ 'References': 'LanguageClient#textDocument_references',
                 \ 'Rename': 'LanguageClient#textDocument_rename',
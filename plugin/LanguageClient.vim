@@ -10,6 +10,10 @@ function! LanguageClient_textDocument_definition(...)
     return call('LanguageClient#textDocument_definition', a:000)
 endfunction
 
+function! LanguageClient_textDocument_declaration(...)
+    return call('LanguageClient#textDocument_declaration', a:000)
+endfunction
+
 function! LanguageClient_textDocument_typeDefinition(...)
     return call('LanguageClient#textDocument_typeDefinition', a:000)
 endfunction
@@ -18,6 +22,14 @@ function! LanguageClient_textDocument_implementation(...)
     return call('LanguageClient#textDocument_implementation', a:000)
 endfunction
 
+function! LanguageClient_callHierarchy_incomingCalls(...)
+    return call('LanguageClient#callHierarchy_incomingCalls', a:000)
+endfunction
+
+function! LanguageClient_callHierarchy_outgoingCalls(...)
+    return call('LanguageClient#callHierarchy_outgoingCalls', a:000)
+endfunction
+
 function! LanguageClient_textDocument_rename(...)
     return call('LanguageClient#textDocument_rename', a:000)
 endfunction
@@ -90,6 +102,10 @@ function! LanguageClient_serverStatusMessage(...)
     return call('LanguageClient#serverStatusMessage', a:000)
 endfunction
 
+function! LanguageClient_statusInfo(...)
+    return call('LanguageClient#statusInfo', a:000)
+endfunction
+
 function! LanguageClient_statusLine(...)
     return call('LanguageClient#statusLine', a:000)
 endfunction
@@ -113,8 +129,10 @@ augroup languageClient
     autocmd!
     autocmd FileType * call LanguageClient#handleFileType()
     autocmd BufNewFile * call LanguageClient#handleBufNewFile()
+    autocmd BufWritePre * call LanguageClient#handleBufWritePre()
     autocmd BufWritePost * call LanguageClient#handleBufWritePost()
     autocmd BufDelete * call LanguageClient#handleBufDelete()
+    autocmd BufEnter * call LanguageClient#handleBufEnter()
     autocmd TextChanged * call LanguageClient#handleTextChanged()
     autocmd TextChangedI * call LanguageClient#handleTextChanged()
     if exists('##TextChangedP')
@@ -111,9 +111,26 @@ def highlight_setup(source: Base, syntax: List[HighlightDefinition]) -> None:
                     source.syntax_name, hl_def.name, hl_def.link))
 
 
+def flatten_document_symbols(symbols: List[Dict]) -> List[Dict]:
+    """Convert hierarchical document symbols to flat symbol information."""
+    flattened = []
+    for symbol in symbols:
+        if "location" in symbol:
+            flattened.append(symbol)
+            continue
+        flattened.append({
+            "name": symbol["name"],
+            "kind": symbol.get("kind", 0),
+            "location": {"uri": "", "range": symbol["selectionRange"]},
+        })
+        flattened.extend(flatten_document_symbols(symbol.get("children", [])))
+    return flattened
+
+
 def convert_symbols_to_candidates(symbols: List[Dict],
                                   bufname: str = None,
                                   pwd: str = None) -> List[Dict]:
+    symbols = flatten_document_symbols(symbols)
     candidates = []
     paths = []
     kinds = []
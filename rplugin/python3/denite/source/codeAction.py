@@ -15,8 +15,19 @@ class Source(Base):
         return [convert_to_candidate(item) for item in result]
 
 
-def convert_to_candidate(cmd: Dict) -> Dict:
-    cmd_str = '{}: {}'.format(cmd['command'], cmd['title'])
+def convert_to_candidate(action: Dict) -> Dict:
+    # Must match the selection entry built by the language client, i.e.,
+    # `command: title` for commands and `kind: title` for code actions.
+    command = action.get('command')
+    if isinstance(command, str):
+        kind = command
+    elif action.get('kind'):
+        kind = action['kind']
+    elif isinstance(command, dict):
+        kind = command['command']
+    else:
+        kind = 'codeAction'
+    cmd_str = '{}: {}'.format(kind, action['title'])
     return {
         'word': cmd_str,
         'action__command': 'call '
@@ -0,0 +1,243 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CallHierarchyDirection {
+    Incoming,
+    Outgoing,
+}
+
+impl FromStr for CallHierarchyDirection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "INCOMING" => Ok(CallHierarchyDirection::Incoming),
+            "OUTGOING" => Ok(CallHierarchyDirection::Outgoing),
+            _ => bail!("Invalid call hierarchy direction: {}", s),
+        }
+    }
+}
+
+impl CallHierarchyDirection {
+    pub fn method(self) -> &'static str {
+        match self {
+            CallHierarchyDirection::Incoming => REQUEST__CallHierarchyIncomingCalls,
+            CallHierarchyDirection::Outgoing => REQUEST__CallHierarchyOutgoingCalls,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CallHierarchyDirection::Incoming => "incoming",
+            CallHierarchyDirection::Outgoing => "outgoing",
+        }
+    }
+}
+
+// Not in lsp-types yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyItem {
+    pub name: String,
+    pub kind: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: String,
+    pub range: Range,
+    pub selection_range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// Node of a call hierarchy, with its children fetched when first expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallHierarchyNode {
+    pub item: CallHierarchyItem,
+    // Call sites, in the caller. For the root, its own selection range.
+    pub call_uri: String,
+    pub call_ranges: Vec<Range>,
+    pub children: Option<Vec<CallHierarchyNode>>,
+    pub expanded: bool,
+}
+
+impl CallHierarchyNode {
+    pub fn new(item: CallHierarchyItem) -> Self {
+        CallHierarchyNode {
+            call_uri: item.uri.clone(),
+            call_ranges: vec![item.selection_range],
+            item,
+            children: None,
+            expanded: false,
+        }
+    }
+
+    /// Parse the result of an incoming or outgoing calls request for `parent`.
+    pub fn from_calls(
+        direction: CallHierarchyDirection,
+        parent: &CallHierarchyItem,
+        result: Value,
+    ) -> Fallible<Vec<Self>> {
+        let calls: Option<Vec<Value>> = serde_json::from_value(result)?;
+        calls
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut call| {
+                let key = match direction {
+                    CallHierarchyDirection::Incoming => "from",
+                    CallHierarchyDirection::Outgoing => "to",
+                };
+                let item: CallHierarchyItem = serde_json::from_value(call[key].take())?;
+                let call_ranges = serde_json::from_value(call["fromRanges"].take())?;
+                // Call sites of outgoing calls are in the parent.
+                let call_uri = match direction {
+                    CallHierarchyDirection::Incoming => item.uri.clone(),
+                    CallHierarchyDirection::Outgoing => parent.uri.clone(),
+                };
+                Ok(CallHierarchyNode {
+                    item,
+                    call_uri,
+                    call_ranges,
+                    children: None,
+                    expanded: false,
+                })
+            })
+            .collect()
+    }
+
+    /// Get a descendant by its path, i.e., the indices of children leading to it.
+    pub fn get(&self, path: &[usize]) -> Option<&Self> {
+        match path.split_first() {
+            None => Some(self),
+            Some((idx, rest)) => self.children.as_ref()?.get(*idx)?.get(rest),
+        }
+    }
+
+    pub fn get_mut(&mut self, path: &[usize]) -> Option<&mut Self> {
+        match path.split_first() {
+            None => Some(self),
+            Some((idx, rest)) => self.children.as_mut()?.get_mut(*idx)?.get_mut(rest),
+        }
+    }
+
+    /// Render this node and its expanded descendants as lines of a tree, together with the
+    /// path of the node on each line.
+    pub fn render(&self) -> (Vec<String>, Vec<Vec<usize>>) {
+        let mut lines = vec![];
+        let mut paths = vec![];
+        self.render_into(&mut vec![], &mut lines, &mut paths);
+        (lines, paths)
+    }
+
+    fn render_into(
+        &self,
+        path: &mut Vec<usize>,
+        lines: &mut Vec<String>,
+        paths: &mut Vec<Vec<usize>>,
+    ) {
+        let marker = match self.children {
+            Some(ref children) if children.is_empty() => " ",
+            _ if self.expanded => "▾",
+            _ => "▸",
+        };
+        let line = self.call_ranges.first().map_or(0, |r| r.start.line) + 1;
+        lines.push(format!(
+            "{}{} {}  {}:{}",
+            "  ".repeat(path.len()),
+            marker,
+            self.item.name,
+            file_name(&self.call_uri),
+            line
+        ));
+        paths.push(path.clone());
+
+        if !self.expanded {
+            return;
+        }
+        for (idx, child) in self.children.iter().flatten().enumerate() {
+            path.push(idx);
+            child.render_into(path, lines, paths);
+            path.pop();
+        }
+    }
+}
+
+fn file_name(uri: &str) -> String {
+    Url::parse(uri)
+        .ok()
+        .and_then(|uri| uri.filepath().ok())
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| uri.to_owned())
+}
+
+/// Call hierarchy shown in the call hierarchy buffer.
+#[derive(Debug, Clone)]
+pub struct CallHierarchy {
+    pub languageId: String,
+    pub direction: CallHierarchyDirection,
+    pub root: CallHierarchyNode,
+    // Path of the node on each line of the buffer.
+    pub paths: Vec<Vec<usize>>,
+}
+
+#[test]
+fn test_call_hierarchy() {
+    let item = |name: &str, uri: &str, line: u64| CallHierarchyItem {
+        name: name.to_owned(),
+        kind: 12,
+        tags: None,
+        detail: None,
+        uri: uri.to_owned(),
+        range: Range::new(Position::new(line, 0), Position::new(line + 2, 1)),
+        selection_range: Range::new(Position::new(line, 3), Position::new(line, 6)),
+        data: None,
+    };
+    let foo = item("foo", "file:///src/foo.rs", 4);
+    let result = json!([
+        {
+            "from": item("bar", "file:///src/bar.rs", 10),
+            "fromRanges": [Range::new(Position::new(11, 4), Position::new(11, 7))],
+        },
+        {
+            "from": item("baz", "file:///src/baz.rs", 20),
+            "fromRanges": [Range::new(Position::new(22, 4), Position::new(22, 7))],
+        },
+    ]);
+
+    let mut root = CallHierarchyNode::new(foo.clone());
+    root.children = Some(
+        CallHierarchyNode::from_calls(CallHierarchyDirection::Incoming, &foo, result).unwrap(),
+    );
+    root.expanded = true;
+    root.get_mut(&[1]).unwrap().children = Some(vec![]);
+    root.get_mut(&[1]).unwrap().expanded = true;
+
+    let (lines, paths) = root.render();
+    assert_eq!(
+        lines,
+        vec![
+            "▾ foo  foo.rs:5",
+            "  ▸ bar  bar.rs:12",
+            "    baz  baz.rs:23"
+        ]
+    );
+    assert_eq!(paths, vec![vec![], vec![0], vec![1]]);
+    assert_eq!(root.get(&[0]).unwrap().call_uri, "file:///src/bar.rs");
+
+    let outgoing = CallHierarchyNode::from_calls(
+        CallHierarchyDirection::Outgoing,
+        &foo,
+        json!([{ "to": item("qux", "file:///src/qux.rs", 1), "fromRanges": [] }]),
+    )
+    .unwrap();
+    assert_eq!(outgoing[0].call_uri, "file:///src/foo.rs");
+    assert!(
+        CallHierarchyNode::from_calls(CallHierarchyDirection::Outgoing, &foo, Value::Null)
+            .unwrap()
+            .is_empty()
+    );
+}
@@ -0,0 +1,322 @@
+//! Client capabilities advertised to language servers on `initialize`.
+//!
+//! Each feature the client implements registers the capabilities it supports in
+//! `CLIENT_FEATURES`. The advertised `ClientCapabilities` are the combination of all registered
+//! features, so whenever a feature is added or extended, its entry here must be updated as well.
+
+use super::*;
+
+/// Runtime facts about the editor that some capabilities depend on.
+#[derive(Debug, Default)]
+pub struct CapabilitiesOptions {
+    pub has_snippet_support: bool,
+}
+
+/// A feature implemented by the client, together with the capabilities it advertises.
+pub struct ClientFeature {
+    pub name: &'static str,
+    pub capabilities: fn(&CapabilitiesOptions) -> Value,
+}
+
+/// All symbol kinds known to the client, i.e., `SymbolKind::File` to `SymbolKind::TypeParameter`.
+fn symbol_kinds() -> Vec<u64> {
+    (1..=26).collect()
+}
+
+/// All completion item kinds known to the client, i.e., `CompletionItemKind::Text` to
+/// `CompletionItemKind::TypeParameter`.
+fn completion_item_kinds() -> Vec<u64> {
+    (1..=25).collect()
+}
+
+pub const CLIENT_FEATURES: &[ClientFeature] = &[
+    ClientFeature {
+        name: "positionEncoding",
+        // `offsetEncoding` is the extension clangd supported before `positionEncoding` was
+        // standardized.
+        capabilities: |_| {
+            json!({
+                "general": {
+                    "positionEncodings": ["utf-8", "utf-32", "utf-16"],
+                },
+                "offsetEncoding": ["utf-8", "utf-32", "utf-16"],
+            })
+        },
+    },
+    ClientFeature {
+        name: "synchronization",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "synchronization": {
+                        "willSave": true,
+                        "willSaveWaitUntil": true,
+                        "didSave": true,
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "pullDiagnostics",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "diagnostic": {
+                        "relatedDocumentSupport": true,
+                    },
+                },
+                "workspace": {
+                    "diagnostics": {
+                        "refreshSupport": true,
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "completion",
+        capabilities: |options| {
+            json!({
+                "textDocument": {
+                    "completion": {
+                        "completionItem": {
+                            "snippetSupport": options.has_snippet_support,
+                        },
+                        "completionItemKind": {
+                            "valueSet": completion_item_kinds(),
+                        },
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "hover",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "hover": {
+                        "contentFormat": [MarkupKind::Markdown, MarkupKind::PlainText],
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "signatureHelp",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "signatureHelp": {
+                        "signatureInformation": {
+                            "parameterInformation": {
+                                "labelOffsetSupport": true,
+                            },
+                        },
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "locations",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "declaration": { "linkSupport": true },
+                    "definition": { "linkSupport": true },
+                    "typeDefinition": { "linkSupport": true },
+                    "implementation": { "linkSupport": true },
+                    "references": {},
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "documentHighlight",
+        capabilities: |_| json!({ "textDocument": { "documentHighlight": {} } }),
+    },
+    ClientFeature {
+        name: "documentSymbol",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "documentSymbol": {
+                        "symbolKind": {
+                            "valueSet": symbol_kinds(),
+                        },
+                        "hierarchicalDocumentSymbolSupport": true,
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "codeAction",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "codeAction": {
+                        "codeActionLiteralSupport": {
+                            "codeActionKind": {
+                                "valueSet": [
+                                    "",
+                                    code_action_kind::QUICKFIX,
+                                    code_action_kind::REFACTOR,
+                                    code_action_kind::REFACTOR_EXTRACT,
+                                    code_action_kind::REFACTOR_INLINE,
+                                    code_action_kind::REFACTOR_REWRITE,
+                                    code_action_kind::SOURCE,
+                                    code_action_kind::SOURCE_ORGANIZE_IMPORTS,
+                                ],
+                            },
+                        },
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        // Not in lsp-types yet.
+        name: "callHierarchy",
+        capabilities: |_| json!({ "textDocument": { "callHierarchy": {} } }),
+    },
+    ClientFeature {
+        name: "formatting",
+        capabilities: |_| {
+            json!({
+                "textDocument": {
+                    "formatting": {},
+                    "rangeFormatting": {},
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "rename",
+        capabilities: |_| json!({ "textDocument": { "rename": {} } }),
+    },
+    ClientFeature {
+        name: "workspaceEdit",
+        capabilities: |_| {
+            json!({
+                "workspace": {
+                    "applyEdit": true,
+                    "workspaceEdit": {
+                        "documentChanges": true,
+                        "resourceOperations": [
+                            ResourceOperationKind::Create,
+                            ResourceOperationKind::Rename,
+                            ResourceOperationKind::Delete,
+                        ],
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "workspaceSymbol",
+        capabilities: |_| {
+            json!({
+                "workspace": {
+                    "symbol": {
+                        "symbolKind": {
+                            "valueSet": symbol_kinds(),
+                        },
+                    },
+                },
+            })
+        },
+    },
+    ClientFeature {
+        name: "executeCommand",
+        capabilities: |_| json!({ "workspace": { "executeCommand": {} } }),
+    },
+    ClientFeature {
+        name: "didChangeConfiguration",
+        capabilities: |_| json!({ "workspace": { "didChangeConfiguration": {} } }),
+    },
+    ClientFeature {
+        name: "didChangeWatchedFiles",
+        capabilities: |_| {
+            json!({
+                "workspace": {
+                    "didChangeWatchedFiles": {
+                        "dynamicRegistration": true,
+                    },
+                },
+            })
+        },
+    },
+];
+
+/// Combine the capabilities of all registered client features.
+pub fn client_capabilities(options: &CapabilitiesOptions) -> Value {
+    CLIENT_FEATURES
+        .iter()
+        .fold(json!({}), |capabilities, feature| {
+            debug!(
+                "Advertising capabilities of client feature: {}",
+                feature.name
+            );
+            capabilities.combine(&(feature.capabilities)(options))
+        })
+}
+
+#[test]
+fn test_client_capabilities() {
+    let capabilities = client_capabilities(&CapabilitiesOptions {
+        has_snippet_support: true,
+    });
+    // Not in lsp-types yet.
+    assert_eq!(
+        capabilities["textDocument"]["diagnostic"]["relatedDocumentSupport"],
+        true
+    );
+    assert_eq!(
+        capabilities["workspace"]["diagnostics"]["refreshSupport"],
+        true
+    );
+    assert!(capabilities["textDocument"]["callHierarchy"].is_object());
+    let capabilities: ClientCapabilities = serde_json::from_value(capabilities).unwrap();
+
+    let text_document = capabilities.text_document.unwrap();
+    assert_eq!(
+        text_document
+            .completion
+            .unwrap()
+            .completion_item
+            .unwrap()
+            .snippet_support,
+        Some(true)
+    );
+    assert_eq!(
+        text_document
+            .document_symbol
+            .unwrap()
+            .hierarchical_document_symbol_support,
+        Some(true)
+    );
+    assert!(text_document
+        .code_action
+        .unwrap()
+        .code_action_literal_support
+        .is_some());
+    assert_eq!(text_document.definition.unwrap().link_support, Some(true));
+    assert_eq!(
+        text_document.hover.unwrap().content_format,
+        Some(vec![MarkupKind::Markdown, MarkupKind::PlainText])
+    );
+
+    let workspace = capabilities.workspace.unwrap();
+    assert_eq!(workspace.apply_edit, Some(true));
+    assert_eq!(
+        workspace.workspace_edit.unwrap().resource_operations,
+        Some(vec![
+            ResourceOperationKind::Create,
+            ResourceOperationKind::Rename,
+            ResourceOperationKind::Delete,
+        ])
+    );
+}
@@ -1,78 +1,395 @@
-use crate::viewport::Viewport;
+use super::*;
+use crate::lsp::request::Request;
 use crate::vim::Vim;
-use failure::Fallible;
-use lazycell::LazyCell;
-use lsp_types::Position;
-use serde::de::DeserializeOwned;
-use serde_json::Value;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
 
+/// Expression to get the text of a buffer, as evaluated when syncing the document.
+pub fn text_exp(filename: &str) -> String {
+    format!("LSP#text('{}')", filename)
+}
+
+/// Expression to get the format of a buffer, as evaluated when syncing the document.
+pub fn document_format_exp(filename: &str) -> String {
+    format!("LSP#document_format('{}')", filename)
+}
+
+thread_local! {
+    /// Keys of values prefetched for the message handled by current thread.
+    static PREFETCHED_KEYS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    /// Number of `gather_args` calls of current thread which would have taken a round-trip
+    /// without prefetching.
+    static SAVED_ROUND_TRIPS: Cell<u64> = Cell::new(0);
+}
+
+/// Number of round-trips saved by prefetching in current thread so far.
+pub fn saved_round_trips() -> u64 {
+    SAVED_ROUND_TRIPS.with(Cell::get)
+}
+
+/// Record that all `keys` were found in params, so that no round-trip was taken for them.
+pub fn note_gathered(keys: &[String]) {
+    let prefetched = PREFETCHED_KEYS.with(|prefetched| {
+        let prefetched = prefetched.borrow();
+        keys.iter().any(|key| prefetched.contains(key))
+    });
+    if prefetched {
+        SAVED_ROUND_TRIPS.with(|count| count.set(count.get() + 1));
+    }
+}
+
+/// Params without the values prefetched for the current message, e.g., to pass them on to a
+/// language server.
+pub fn without_prefetched(params: &Value) -> Value {
+    let mut params = params.clone();
+    if let Some(map) = params.as_object_mut() {
+        PREFETCHED_KEYS.with(|prefetched| {
+            for key in prefetched.borrow().iter() {
+                map.remove(key);
+            }
+        });
+    }
+    params
+}
+
+// Arguments handlers gather. These are shared with `PREFETCHES`, so the two can't drift apart.
+
+/// Vars to sync a document, i.e., to call `textDocument_didChange`.
+pub const BUFFER_ARGS: &[VimVar] = &[VimVar::LanguageId, VimVar::Filename];
+
+pub const LANGUAGE_ARGS: &[VimVar] = &[VimVar::LanguageId];
+
+pub const POINT_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::Line,
+    VimVar::Character,
+];
+
+pub const CURSOR_ARGS: &[VimVar] = &[VimVar::Filename, VimVar::Line, VimVar::Character];
+
+pub const POSITION_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::Line,
+    VimVar::Character,
+    VimVar::Handle,
+];
+
+pub const FIND_LOCATIONS_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::Cword,
+    VimVar::Line,
+    VimVar::Character,
+    VimVar::Handle,
+    VimVar::GotoCmd,
+    VimVar::Peek,
+];
+
+pub const RENAME_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::Line,
+    VimVar::Character,
+    VimVar::Cword,
+    VimVar::NewName,
+    VimVar::Handle,
+];
+
+pub const RELATED_INFORMATION_ARGS: &[VimVar] = &[
+    VimVar::Filename,
+    VimVar::Line,
+    VimVar::Character,
+    VimVar::GotoCmd,
+];
+
+pub const DOCUMENT_ARGS: &[VimVar] = &[VimVar::LanguageId, VimVar::Filename, VimVar::Handle];
+
+pub const FORMATTING_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::Handle,
+    VimVar::TabSize,
+    VimVar::InsertSpaces,
+];
+
+/// Indentation options for formatting.
+pub const INDENT_ARGS: &[VimVar] = &[VimVar::TabSize, VimVar::InsertSpaces];
+
+/// `BUFFER_ARGS` and `INDENT_ARGS`, as pre-write actions might format the document.
+pub const PRE_WRITE_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::TabSize,
+    VimVar::InsertSpaces,
+];
+
+pub const WORKSPACE_SYMBOL_ARGS: &[VimVar] = &[VimVar::LanguageId, VimVar::Handle];
+
+pub const DID_OPEN_ARGS: &[VimVar] = &[
+    VimVar::LanguageId,
+    VimVar::Filename,
+    VimVar::Text,
+    VimVar::DocumentFormat,
+];
+
+/// What handlers of a message from vim evaluate in the editor.
+struct Prefetch {
+    method: &'static str,
+    vars: &'static [VimVar],
+    /// Whether the handler syncs the document first, i.e., calls `textDocument_didChange`.
+    sync_document: bool,
+}
+
+const PREFETCHES: &[Prefetch] = &[
+    Prefetch {
+        method: lsp::request::HoverRequest::METHOD,
+        vars: POSITION_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::DocumentHighlightRequest::METHOD,
+        vars: POSITION_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::CodeActionRequest::METHOD,
+        vars: POSITION_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::Completion::METHOD,
+        vars: POSITION_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::SignatureHelpRequest::METHOD,
+        vars: POSITION_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: REQUEST__FindLocations,
+        vars: FIND_LOCATIONS_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: REQUEST__CallHierarchy,
+        vars: POINT_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::Rename::METHOD,
+        vars: RENAME_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::DocumentSymbolRequest::METHOD,
+        vars: DOCUMENT_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::Formatting::METHOD,
+        vars: FORMATTING_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::RangeFormatting::METHOD,
+        vars: FORMATTING_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: lsp::request::WorkspaceSymbol::METHOD,
+        vars: WORKSPACE_SYMBOL_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: REQUEST__ExplainErrorAtPoint,
+        vars: CURSOR_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: REQUEST__GotoRelatedInformation,
+        vars: RELATED_INFORMATION_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: REQUEST__ExportDiagnostics,
+        vars: BUFFER_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: REQUEST__GotoDiagnostic,
+        vars: CURSOR_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: REQUEST__WriteDiagnosticsBaseline,
+        vars: LANGUAGE_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: NOTIFICATION__HandleFileType,
+        vars: DID_OPEN_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: REQUEST__HandleBufWritePre,
+        vars: PRE_WRITE_ARGS,
+        sync_document: true,
+    },
+    Prefetch {
+        method: NOTIFICATION__HandleBufEnter,
+        vars: BUFFER_ARGS,
+        sync_document: false,
+    },
+    Prefetch {
+        method: NOTIFICATION__HandleBufWritePost,
+        vars: BUFFER_ARGS,
+        sync_document: true,
+    },
+];
+
+/// Parameters of a message from vim, completed with everything its handler evaluates in the
+/// editor, so that handlers (through `gather_args`) don't need further round-trips.
 pub struct Context {
-    vim: Vim,
-    bufname: String,
-    language_id: LazyCell<String>,
-    viewport: LazyCell<Viewport>,
-    position: LazyCell<Position>,
-    current_word: LazyCell<String>,
-    text: LazyCell<Vec<String>>,
-    prepopulated: HashMap<String, Value>,
+    method: String,
+    params: serde_json::Map<String, Value>,
 }
 
 impl Context {
-    pub fn new(bufname: String, vim: Vim) -> Self {
+    pub fn new(method: &str, params: &Value) -> Self {
         Context {
-            vim,
-            bufname,
-            language_id: LazyCell::new(),
-            viewport: LazyCell::new(),
-            position: LazyCell::new(),
-            current_word: LazyCell::new(),
-            text: LazyCell::new(),
-            prepopulated: HashMap::new(),
+            method: method.to_owned(),
+            params: params.as_object().cloned().unwrap_or_default(),
         }
     }
 
-    /// Try get result of expression from prepopulated.
-    fn try_get<R: DeserializeOwned>(&self, expr: &str) -> Fallible<Option<R>> {
-        if let Some(value) = self.prepopulated.get(expr) {
-            Ok(Some(serde_json::from_value(value.clone())?))
-        } else {
-            Ok(None)
+    /// Keys and expressions of values the handler needs, but which are not in params.
+    pub fn missing(&self) -> Vec<(String, String)> {
+        let prefetch = match PREFETCHES.iter().find(|p| p.method == self.method) {
+            Some(prefetch) => prefetch,
+            None => return vec![],
+        };
+
+        let mut vars: Vec<&VimVar> = prefetch.vars.iter().collect();
+        if prefetch.sync_document {
+            vars.extend(
+                BUFFER_ARGS
+                    .iter()
+                    .filter(|var| !prefetch.vars.contains(var)),
+            );
+        }
+        let mut exps: Vec<(String, String)> = vars
+            .into_iter()
+            .map(|var| (var.to_key(), var.to_exp()))
+            .collect();
+        // Document text is keyed by filename, which is only known upfront if sent by vim.
+        if prefetch.sync_document {
+            if let Some(filename) = self.params.get("filename").and_then(Value::as_str) {
+                for exp in &[text_exp(filename), document_format_exp(filename)] {
+                    exps.push((exp.clone(), exp.clone()));
+                }
+            }
         }
-    }
 
-    pub fn get_languageId(&self) -> Fallible<&String> {
-        let expr = "&filetype";
+        exps.into_iter()
+            .filter(|(key, _)| !self.params.contains_key(key))
+            .collect()
+    }
 
-        self.language_id.try_borrow_with(|| {
-            self.try_get(expr)?
-                .map_or_else(|| self.vim.getbufvar(&self.bufname, expr), Ok)
-        })
+    /// Evaluate all missing values in a single round-trip, and get the completed params.
+    pub fn prefetch(mut self, vim: &Vim) -> Fallible<Value> {
+        let (keys, exps): (Vec<_>, Vec<_>) = self.missing().into_iter().unzip();
+        if !exps.is_empty() {
+            info!("Prefetching for {}: {:?}", self.method, exps);
+            let values: Vec<Value> = vim.eval::<&[_], _>(exps.as_ref())?;
+            self.params.extend(keys.iter().cloned().zip(values));
+        }
+        PREFETCHED_KEYS.with(|prefetched| *prefetched.borrow_mut() = keys);
+        Ok(Value::Object(self.params))
     }
+}
 
-    pub fn get_viewport(&self) -> Fallible<&Viewport> {
-        let expr = "LSP#viewport()";
+#[test]
+fn test_context_missing() {
+    let params = json!({
+        "languageId": "rust",
+        "filename": "/tmp/a.rs",
+        "line": 1,
+        "character": 2,
+        "handle": true,
+    });
+    assert_eq!(
+        Context::new(lsp::request::HoverRequest::METHOD, &params).missing(),
+        vec![
+            (
+                "LSP#text('/tmp/a.rs')".to_owned(),
+                "LSP#text('/tmp/a.rs')".to_owned()
+            ),
+            (
+                "LSP#document_format('/tmp/a.rs')".to_owned(),
+                "LSP#document_format('/tmp/a.rs')".to_owned()
+            ),
+        ]
+    );
 
-        self.viewport
-            .try_borrow_with(|| self.try_get(expr)?.map_or_else(|| self.vim.eval(expr), Ok))
-    }
+    assert_eq!(
+        Context::new(REQUEST__ExplainErrorAtPoint, &json!({})).missing(),
+        vec![
+            ("filename".to_owned(), "LSP#filename()".to_owned()),
+            ("line".to_owned(), "LSP#line()".to_owned()),
+            ("character".to_owned(), "LSP#character()".to_owned()),
+        ]
+    );
 
-    pub fn get_position(&self) -> Fallible<&Position> {
-        let expr = "LSP#position()";
+    // References are found without peeking, as they don't send the option.
+    let references = json!({
+        "method": lsp::request::References::METHOD,
+        "languageId": "rust",
+        "filename": "/tmp/a.rs",
+        "cword": "a",
+        "line": 1,
+        "character": 2,
+        "handle": true,
+        "gotoCmd": null,
+        "LSP#text('/tmp/a.rs')": [],
+        "LSP#document_format('/tmp/a.rs')": {},
+    });
+    assert_eq!(
+        Context::new(REQUEST__FindLocations, &references).missing(),
+        vec![("peek".to_owned(), "0".to_owned())]
+    );
 
-        self.position
-            .try_borrow_with(|| self.try_get(expr)?.map_or_else(|| self.vim.eval(expr), Ok))
-    }
+    // Handlers syncing the document need its filename, even if they don't use it.
+    assert_eq!(
+        Context::new(
+            lsp::request::WorkspaceSymbol::METHOD,
+            &json!({ "languageId": "rust", "handle": true })
+        )
+        .missing(),
+        vec![("filename".to_owned(), "LSP#filename()".to_owned())]
+    );
 
-    pub fn get_current_word(&self) -> Fallible<&String> {
-        let expr = "expand('<cword>')";
+    assert!(Context::new(REQUEST__GetState, &params)
+        .missing()
+        .is_empty());
+}
 
-        self.current_word
-            .try_borrow_with(|| self.try_get(expr)?.map_or_else(|| self.vim.eval(expr), Ok))
-    }
+#[test]
+fn test_prefetched_keys() {
+    PREFETCHED_KEYS.with(|prefetched| {
+        *prefetched.borrow_mut() = vec!["peek".to_owned(), text_exp("/tmp/a.rs")];
+    });
+    let params = json!({
+        "languageId": "rust",
+        "peek": 0,
+        "LSP#text('/tmp/a.rs')": ["fn main() {}"],
+    });
+    assert_eq!(without_prefetched(&params), json!({ "languageId": "rust" }));
 
-    pub fn get_text(&self, start: &str, end: &str) -> Fallible<&Vec<String>> {
-        self.text
-            .try_borrow_with(|| self.vim.getbufline(&self.bufname, start, end))
-    }
+    let saved = saved_round_trips();
+    note_gathered(&["languageId".to_owned()]);
+    assert_eq!(saved_round_trips(), saved);
+    note_gathered(&["languageId".to_owned(), "peek".to_owned()]);
+    assert_eq!(saved_round_trips(), saved + 1);
 }
@@ -0,0 +1,261 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Json,
+    Sarif,
+    Checkstyle,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "JSON" => Ok(ExportFormat::Json),
+            "SARIF" => Ok(ExportFormat::Sarif),
+            "CHECKSTYLE" => Ok(ExportFormat::Checkstyle),
+            _ => bail!("Invalid export format: {}", s),
+        }
+    }
+}
+
+/// Serialise diagnostics of files, given as (path, diagnostics, lines). Paths are relative to
+/// project root if given, or else absolute. Diagnostics are in editor (byte) columns.
+///
+/// Lines and columns are 1-based. Columns are in bytes, except in SARIF, where they are in UTF-16
+/// code units, its default column kind.
+pub fn export_diagnostics(
+    format: ExportFormat,
+    root: Option<&str>,
+    files: &[(String, Vec<Diagnostic>, Vec<String>)],
+) -> Fallible<String> {
+    let output = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&to_json(files))?,
+        ExportFormat::Sarif => serde_json::to_string_pretty(&to_sarif(root, files)?)?,
+        ExportFormat::Checkstyle => to_checkstyle(files),
+    };
+    Ok(output)
+}
+
+fn severity_name(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic.severity.unwrap_or(DiagnosticSeverity::Hint) {
+        DiagnosticSeverity::Error => "error",
+        DiagnosticSeverity::Warning => "warning",
+        DiagnosticSeverity::Information => "info",
+        DiagnosticSeverity::Hint => "hint",
+    }
+}
+
+fn to_json(files: &[(String, Vec<Diagnostic>, Vec<String>)]) -> Value {
+    let mut entries = vec![];
+    for (path, diagnostics, _) in files {
+        for dn in diagnostics {
+            entries.push(json!({
+                "path": path,
+                "line": dn.range.start.line + 1,
+                "column": dn.range.start.character + 1,
+                "endLine": dn.range.end.line + 1,
+                "endColumn": dn.range.end.character + 1,
+                "severity": severity_name(dn),
+                "source": dn.source,
+                "code": dn.code.as_ref().map(ToString::to_string),
+                "message": dn.message,
+            }));
+        }
+    }
+    Value::Array(entries)
+}
+
+/// Relative paths are resolved against the project root by the `SRCROOT` base id.
+fn to_sarif(
+    root: Option<&str>,
+    files: &[(String, Vec<Diagnostic>, Vec<String>)],
+) -> Fallible<Value> {
+    let mut results = vec![];
+    for (path, diagnostics, lines) in files {
+        let to_column = |position: &Position| {
+            lines
+                .get(position.line as usize)
+                .map_or(position.character, |text| {
+                    byte_to_character(text, position.character, PositionEncoding::Utf16)
+                })
+        };
+        let artifactLocation = if root.is_some() {
+            json!({ "uri": path, "uriBaseId": "SRCROOT" })
+        } else {
+            json!({ "uri": path.to_url()?.as_str() })
+        };
+        for dn in diagnostics {
+            let level = match dn.severity.unwrap_or(DiagnosticSeverity::Hint) {
+                DiagnosticSeverity::Error => "error",
+                DiagnosticSeverity::Warning => "warning",
+                DiagnosticSeverity::Information | DiagnosticSeverity::Hint => "note",
+            };
+            let mut result = json!({
+                "level": level,
+                "message": { "text": dn.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": artifactLocation,
+                        "region": {
+                            "startLine": dn.range.start.line + 1,
+                            "startColumn": to_column(&dn.range.start) + 1,
+                            "endLine": dn.range.end.line + 1,
+                            "endColumn": to_column(&dn.range.end) + 1,
+                        },
+                    },
+                }],
+            });
+            if let Some(ref code) = dn.code {
+                result["ruleId"] = json!(code.to_string());
+            }
+            if let Some(ref source) = dn.source {
+                result["properties"] = json!({ "source": source });
+            }
+            results.push(result);
+        }
+    }
+
+    let mut run = json!({
+        "tool": {
+            "driver": {
+                "name": "LanguageClient-neovim",
+                "informationUri": "https://github.com/autozimu/LanguageClient-neovim",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        },
+        "results": results,
+    });
+    if let Some(root) = root {
+        let uri = Url::from_directory_path(root)
+            .map_err(|_| format_err!("Failed to convert to url: {}", root))?;
+        run["originalUriBaseIds"] = json!({ "SRCROOT": { "uri": uri.as_str() } });
+    }
+
+    Ok(json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [run],
+    }))
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            '\n' => escaped += "&#10;",
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn to_checkstyle(files: &[(String, Vec<Diagnostic>, Vec<String>)]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += "<checkstyle version=\"4.3\">\n";
+    for (path, diagnostics, _) in files {
+        xml += &format!("  <file name=\"{}\">\n", escape_xml(path));
+        for dn in diagnostics {
+            // Checkstyle has no hint severity.
+            let severity = match severity_name(dn) {
+                "hint" => "info",
+                severity => severity,
+            };
+            let mut source = dn.source.clone().unwrap_or_default();
+            if let Some(ref code) = dn.code {
+                if !source.is_empty() {
+                    source += ".";
+                }
+                source += &code.to_string();
+            }
+            xml += &format!(
+                "    <error line=\"{}\" column=\"{}\" severity=\"{}\" message=\"{}\" source=\"{}\"/>\n",
+                dn.range.start.line + 1,
+                dn.range.start.character + 1,
+                severity,
+                escape_xml(&dn.message),
+                escape_xml(&source),
+            );
+        }
+        xml += "  </file>\n";
+    }
+    xml += "</checkstyle>\n";
+    xml
+}
+
+#[test]
+fn test_export_diagnostics() {
+    let files = vec![(
+        "src/main.rs".to_owned(),
+        vec![Diagnostic {
+            range: Range::new(Position::new(1, 4), Position::new(1, 8)),
+            severity: Some(DiagnosticSeverity::Error),
+            code: Some(NumberOrString::String("E0308".to_owned())),
+            source: Some("rustc".to_owned()),
+            message: "expected `u8`, found \"&str\"".to_owned(),
+            ..Diagnostic::default()
+        }],
+        vec!["fn main() {".to_owned(), "  é x: u8 = \"\";".to_owned()],
+    )];
+
+    let json: Value = serde_json::from_str(
+        &export_diagnostics(ExportFormat::Json, Some("/tmp/project"), &files).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        json,
+        json!([{
+            "path": "src/main.rs",
+            "line": 2,
+            "column": 5,
+            "endLine": 2,
+            "endColumn": 9,
+            "severity": "error",
+            "source": "rustc",
+            "code": "E0308",
+            "message": "expected `u8`, found \"&str\"",
+        }])
+    );
+
+    let sarif: Value = serde_json::from_str(
+        &export_diagnostics(ExportFormat::Sarif, Some("/tmp/project"), &files).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        sarif["runs"][0]["originalUriBaseIds"],
+        json!({ "SRCROOT": { "uri": "file:///tmp/project/" } })
+    );
+    let result = &sarif["runs"][0]["results"][0];
+    assert_eq!(result["ruleId"], "E0308");
+    assert_eq!(result["level"], "error");
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["artifactLocation"],
+        json!({ "uri": "src/main.rs", "uriBaseId": "SRCROOT" })
+    );
+    // In UTF-16 code units, `é` being two bytes but one code unit.
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["region"]["startColumn"],
+        4
+    );
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["region"]["endColumn"],
+        8
+    );
+
+    assert_eq!(
+        export_diagnostics(ExportFormat::Checkstyle, Some("/tmp/project"), &files).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<checkstyle version="4.3">
+  <file name="src/main.rs">
+    <error line="2" column="5" severity="error" message="expected `u8`, found &quot;&amp;str&quot;" source="rustc.E0308"/>
+  </file>
+</checkstyle>
+"#
+    );
+}
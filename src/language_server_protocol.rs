@@ -54,6 +54,7 @@ impl LanguageClient {
             }
         }
         let values_request: Vec<Value> = if keys_request.is_empty() {
+            note_gathered(&exps.iter().map(VimExp::to_key).collect::<Vec<_>>());
             vec![]
         } else {
             info!(
@@ -152,21 +153,67 @@ impl LanguageClient {
             .as_ref(),
         )?;
 
-        let (diagnosticsSignsMax, documentHighlightDisplay, selectionUI_autoOpen, use_virtual_text): (
+        #[allow(clippy::type_complexity)]
+        let (
+            diagnosticsSignsMax,
+            documentHighlightDisplay,
+            selectionUI_autoOpen,
+            use_virtual_text,
+            preWriteActions,
+            pre_write_timeout,
+            virtualDocumentMethods,
+            diagnosticsFilters,
+            virtualTextPrefix,
+            virtualTextFormat,
+            virtualTextMaxLength,
+            virtualTextHighestSeverityOnly,
+            virtualTextCurrentLineOnly,
+            diagnosticsBaselinePath,
+        ): (
             Option<u64>,
             Value,
             u8,
             u8,
+            HashMap<String, Vec<String>>,
+            f64,
+            HashMap<String, String>,
+            Value,
+            String,
+            String,
+            Option<u64>,
+            u8,
+            u8,
+            String,
         ) = self.vim()?.eval(
             [
                 "get(g:, 'LanguageClient_diagnosticsSignsMax', v:null)",
                 "get(g:, 'LanguageClient_documentHighlightDisplay', {})",
                 "!!s:GetVar('LanguageClient_selectionUI_autoOpen', 1)",
                 "s:useVirtualText()",
+                "get(g:, 'LanguageClient_preWriteActions', {})",
+                "get(g:, 'LanguageClient_preWriteTimeout', 1)",
+                "get(g:, 'LanguageClient_virtualDocumentMethods', {})",
+                "get(g:, 'LanguageClient_diagnosticsFilters', {})",
+                "get(g:, 'LanguageClient_virtualTextPrefix', '')",
+                "get(g:, 'LanguageClient_virtualTextFormat', '{message}')",
+                "get(g:, 'LanguageClient_virtualTextMaxLength', v:null)",
+                "!!get(g:, 'LanguageClient_virtualTextHighestSeverityOnly', 0)",
+                "!!get(g:, 'LanguageClient_virtualTextCurrentLineOnly', 0)",
+                "expand(get(g:, 'LanguageClient_diagnosticsBaselinePath', '.vim/diagnostics-baseline.json'))",
             ]
             .as_ref(),
         )?;
 
+        let (diagnosticsSignsPriority, diagnosticsSignsCombine, sign_groups): (u64, u8, u8) =
+            self.vim()?.eval(
+                [
+                    "get(g:, 'LanguageClient_diagnosticsSignsPriority', 10)",
+                    "!!get(g:, 'LanguageClient_diagnosticsSignsCombine', 0)",
+                    "exists('*sign_place')",
+                ]
+                .as_ref(),
+            )?;
+
         // vimscript use 1 for true, 0 for false.
         let autoStart = autoStart == 1;
         let selectionUI_autoOpen = selectionUI_autoOpen == 1;
@@ -194,9 +241,19 @@ impl LanguageClient {
         let change_throttle = change_throttle.map(|t| Duration::from_millis((t * 1000.0) as u64));
         let wait_output_timeout =
             Duration::from_millis((wait_output_timeout.unwrap_or(10.0) * 1000.0) as u64);
+        let pre_write_timeout = Duration::from_millis((pre_write_timeout * 1000.0) as u64);
 
         let diagnosticsEnable = diagnosticsEnable == 1;
 
+        // Patterns are compiled here, once.
+        let diagnosticsFilters: HashMap<String, DiagnosticsFilter> =
+            serde_json::from_value(diagnosticsFilters).with_context(|err| {
+                format!(
+                    "Invalid option for LanguageClient_diagnosticsFilters: {}",
+                    err
+                )
+            })?;
+
         let diagnosticsList = if let Some(s) = diagnosticsList {
             DiagnosticsList::from_str(&s)?
         } else {
@@ -235,13 +292,18 @@ impl LanguageClient {
             state.diagnosticsDisplay = serde_json::from_value(
                 serde_json::to_value(&state.diagnosticsDisplay)?.combine(&diagnosticsDisplay),
             )?;
+            state.diagnosticsFilters = diagnosticsFilters;
             state.diagnosticsSignsMax = diagnosticsSignsMax;
+            state.diagnosticsSignsPriority = diagnosticsSignsPriority;
+            state.diagnosticsSignsCombine = diagnosticsSignsCombine == 1;
+            state.sign_groups = sign_groups == 1;
             state.documentHighlightDisplay = serde_json::from_value(
                 serde_json::to_value(&state.documentHighlightDisplay)?
                     .combine(&documentHighlightDisplay),
             )?;
             state.windowLogMessageLevel = windowLogMessageLevel;
             state.settingsPath = settingsPath;
+            state.diagnosticsBaselinePath = diagnosticsBaselinePath;
             state.loadSettings = loadSettings;
             state.rootMarkers = rootMarkers;
             state.change_throttle = change_throttle;
@@ -249,6 +311,14 @@ impl LanguageClient {
             state.hoverPreview = hoverPreview;
             state.completionPreferTextEdit = completionPreferTextEdit;
             state.use_virtual_text = use_virtual_text == 1;
+            state.virtualTextPrefix = virtualTextPrefix;
+            state.virtualTextFormat = virtualTextFormat;
+            state.virtualTextMaxLength = virtualTextMaxLength;
+            state.virtualTextHighestSeverityOnly = virtualTextHighestSeverityOnly == 1;
+            state.virtualTextCurrentLineOnly = virtualTextCurrentLineOnly == 1;
+            state.preWriteActions = preWriteActions;
+            state.pre_write_timeout = pre_write_timeout;
+            state.virtualDocumentMethods.extend(virtualDocumentMethods);
             state.loggingFile = loggingFile;
             state.loggingLevel = loggingLevel;
             state.serverStderr = serverStderr;
@@ -283,6 +353,12 @@ impl LanguageClient {
                 "sign define LanguageClient{} text={} texthl={}",
                 entry.name, entry.signText, entry.signTexthl,
             ));
+            // Marks lines also having diagnostics of lower severities.
+            let text: String = entry.signText.chars().take(1).collect();
+            cmds.push(format!(
+                "sign define LanguageClient{}Combined text={}+ texthl={}",
+                entry.name, text, entry.signTexthl,
+            ));
         }
 
         self.vim()?.command(cmds)?;
@@ -306,10 +382,12 @@ impl LanguageClient {
                 }
                 DocumentChanges::Operations(ref ops) => {
                     for op in ops {
-                        if let DocumentChangeOperation::Edit(ref e) = op {
-                            self.apply_TextEdits(&e.text_document.uri.filepath()?, &e.edits)?;
+                        match op {
+                            DocumentChangeOperation::Edit(ref e) => {
+                                self.apply_TextEdits(&e.text_document.uri.filepath()?, &e.edits)?;
+                            }
+                            DocumentChangeOperation::Op(ref op) => self.apply_ResourceOp(op)?,
                         }
-                        // TODO: handle ResourceOp.
                     }
                 }
             }
@@ -318,34 +396,112 @@ impl LanguageClient {
                 self.apply_TextEdits(&uri.filepath()?, edits)?;
             }
         }
-        self.vim()?.edit(&None, &filename)?;
+        self.edit(&None, &filename)?;
         self.vim()?.cursor(line + 1, character + 1)?;
         debug!("End apply WorkspaceEdit");
         Ok(())
     }
 
+    fn apply_ResourceOp(&self, op: &ResourceOp) -> Fallible<()> {
+        debug!("Begin apply ResourceOp: {:?}", op);
+        match op {
+            ResourceOp::Create(ref op) => {
+                let path = op.uri.filepath()?;
+                let (overwrite, ignore_if_exists) = op
+                    .options
+                    .as_ref()
+                    .map(|o| {
+                        (
+                            o.overwrite.unwrap_or_default(),
+                            o.ignore_if_exists.unwrap_or_default(),
+                        )
+                    })
+                    .unwrap_or_default();
+                if path.exists() && !overwrite {
+                    if ignore_if_exists {
+                        return Ok(());
+                    }
+                    bail!("Failed to create {}: file already exists", path.display());
+                }
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                File::create(&path)?;
+            }
+            ResourceOp::Rename(ref op) => {
+                let old_path = op.old_uri.filepath()?;
+                let new_path = op.new_uri.filepath()?;
+                let (overwrite, ignore_if_exists) = op
+                    .options
+                    .as_ref()
+                    .map(|o| {
+                        (
+                            o.overwrite.unwrap_or_default(),
+                            o.ignore_if_exists.unwrap_or_default(),
+                        )
+                    })
+                    .unwrap_or_default();
+                if new_path.exists() && !overwrite {
+                    if ignore_if_exists {
+                        return Ok(());
+                    }
+                    bail!(
+                        "Failed to rename {} to {}: target already exists",
+                        old_path.display(),
+                        new_path.display()
+                    );
+                }
+                if let Some(parent) = new_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&old_path, &new_path)?;
+            }
+            ResourceOp::Delete(ref op) => {
+                let path = op.uri.filepath()?;
+                let (recursive, ignore_if_not_exists) = op
+                    .options
+                    .as_ref()
+                    .map(|o| {
+                        (
+                            o.recursive.unwrap_or_default(),
+                            o.ignore_if_not_exists.unwrap_or_default(),
+                        )
+                    })
+                    .unwrap_or_default();
+                if !path.exists() {
+                    if ignore_if_not_exists {
+                        return Ok(());
+                    }
+                    bail!("Failed to delete {}: file does not exist", path.display());
+                }
+                if !path.is_dir() {
+                    std::fs::remove_file(&path)?;
+                } else if recursive {
+                    std::fs::remove_dir_all(&path)?;
+                } else {
+                    std::fs::remove_dir(&path)?;
+                }
+            }
+        }
+        debug!("End apply ResourceOp");
+        Ok(())
+    }
+
     pub fn textDocument_documentHighlight(&self, params: &Value) -> Fallible<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::DocumentHighlightRequest::METHOD);
         let (languageId, filename, line, character, handle): (String, String, u64, u64, bool) =
-            self.gather_args(
-                &[
-                    VimVar::LanguageId,
-                    VimVar::Filename,
-                    VimVar::Line,
-                    VimVar::Character,
-                    VimVar::Handle,
-                ],
-                params,
-            )?;
+            self.gather_args(POSITION_ARGS, params)?;
 
-        let result = self.get_client(&Some(languageId))?.call(
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
+
+        let result = self.get_client(&Some(languageId.clone()))?.call(
             lsp::request::DocumentHighlightRequest::METHOD,
             TextDocumentPositionParams {
                 text_document: TextDocumentIdentifier {
                     uri: filename.to_url()?,
                 },
-                position: Position { line, character },
+                position,
             },
         )?;
 
@@ -358,13 +514,14 @@ impl LanguageClient {
         if let Some(document_highlight) = document_highlight {
             let documentHighlightDisplay =
                 self.get(|state| state.documentHighlightDisplay.clone())?;
+            let to_column = self.get_column_converter(&languageId, &filename)?;
             let highlights = document_highlight
                 .into_iter()
                 .map(|DocumentHighlight { range, kind }| {
                     Ok(Highlight {
                         line: range.start.line,
-                        character_start: range.start.character,
-                        character_end: range.end.character,
+                        character_start: to_column(&range.start),
+                        character_end: to_column(&range.end),
                         group: documentHighlightDisplay
                             .get(
                                 &kind
@@ -463,15 +620,43 @@ impl LanguageClient {
 
         let mut lines: Vec<String> = self.vim()?.rpcclient.call("getline", json!([1, '$']))?;
         let lines_len_prev = lines.len();
-        let fixendofline = self.vim()?.eval::<_, u8>("&fixendofline")? == 1;
-        if lines.last().map(String::is_empty) == Some(false) && fixendofline {
+        // The server sees the text as written to disk, where a final line ending starts one more
+        // (empty) line.
+        let format: DocumentFormat = self.vim()?.eval(VimVar::DocumentFormat)?;
+        if format.has_final_line_ending() && lines != [""] {
             lines.push("".to_owned());
         }
+        for edit in &mut edits {
+            edit.new_text = format.normalize(&edit.new_text);
+        }
+
+        // Edits count characters in the negotiated position encoding, convert them into bytes.
+        let languageId: String = self.vim()?.eval("&filetype")?;
+        let encoding = self.get_position_encoding(&languageId)?;
+        if encoding != PositionEncoding::Utf8 {
+            for edit in &mut edits {
+                for position in &mut [&mut edit.range.start, &mut edit.range.end] {
+                    if let Some(text) = lines.get(position.line.to_usize()?) {
+                        position.character = character_to_byte(text, position.character, encoding);
+                    }
+                }
+            }
+        }
 
-        let mut lines = apply_TextEdits(&lines, &edits)?;
+        let text = lines.join("\n");
+        let lines = apply_TextEdits(&lines, &edits)?;
 
-        if lines.last().map(String::is_empty) == Some(true) && fixendofline {
-            lines.pop();
+        // Whether the edits added or removed the final line ending only matters when vim doesn't
+        // fix it when writing.
+        if !format.fix_end_of_line && !lines.is_empty() {
+            let terminated = apply_TextEdits_to_text(&text, &edits)?.ends_with('\n');
+            if terminated != format.end_of_line {
+                self.vim()?.command(if terminated {
+                    "setlocal endofline"
+                } else {
+                    "setlocal noendofline"
+                })?;
+            }
         }
         if lines.len() < lines_len_prev {
             self.vim()?
@@ -483,32 +668,56 @@ impl LanguageClient {
     }
 
     fn update_quickfixlist(&self) -> Fallible<()> {
-        let diagnostics = self.get(|state| state.diagnostics.clone())?;
-        let qflist: Vec<_> = diagnostics
-            .iter()
-            .flat_map(|(filename, diagnostics)| {
-                diagnostics
-                    .iter()
-                    .map(|dn| QuickfixEntry {
-                        filename: filename.to_owned(),
-                        lnum: dn.range.start.line + 1,
-                        col: Some(dn.range.start.character + 1),
-                        nr: dn.code.clone().map(|ns| ns.to_string()),
-                        text: Some(dn.message.to_owned()),
-                        typ: dn.severity.map(|sev| sev.to_quickfix_entry_type()),
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect();
+        let (diagnostics, displays) = self.get(|state| {
+            (
+                state.diagnostics.clone(),
+                state.diagnostics_displays.clone(),
+            )
+        })?;
+        let mut filenames: Vec<_> = diagnostics.keys().collect();
+        filenames.sort();
+        let mut qflist = vec![];
+        for filename in filenames {
+            let mut diagnostics: Vec<_> = diagnostics[filename]
+                .iter()
+                .zip(displays.get(filename).into_iter().flatten())
+                .filter(|(_, displays)| displays.list)
+                .map(|(dn, _)| dn)
+                .collect();
+            diagnostics.sort_by_key(|dn| (dn.range.start.line, dn.range.start.character));
+            for dn in diagnostics {
+                qflist.push(QuickfixEntry {
+                    filename: filename.to_owned(),
+                    lnum: dn.range.start.line + 1,
+                    col: Some(dn.range.start.character + 1),
+                    nr: dn.code.clone().map(|ns| ns.to_string()),
+                    text: Some(dn.message.to_owned()),
+                    typ: dn.severity.map(|sev| sev.to_quickfix_entry_type()),
+                });
+
+                // Related information follows the diagnostic.
+                for info in dn.related_information.iter().flatten() {
+                    let loc = &info.location;
+                    qflist.push(QuickfixEntry {
+                        filename: loc.uri.filepath()?.to_string_lossy().into_owned(),
+                        lnum: loc.range.start.line + 1,
+                        col: Some(loc.range.start.character + 1),
+                        nr: None,
+                        text: Some(format!("↳ {}", info.message)),
+                        typ: None,
+                    });
+                }
+            }
+        }
 
         let title = "[LC]: diagnostics";
         let diagnosticsList = self.get(|state| state.diagnosticsList)?;
         match diagnosticsList {
             DiagnosticsList::Quickfix => {
-                self.vim()?.setqflist(&qflist, "r", title)?;
+                self.vim()?.update_list(&qflist, title, false)?;
             }
             DiagnosticsList::Location => {
-                self.vim()?.setloclist(&qflist, "r", title)?;
+                self.vim()?.update_list(&qflist, title, true)?;
             }
             DiagnosticsList::Disabled => {}
         }
@@ -516,10 +725,20 @@ impl LanguageClient {
         Ok(())
     }
 
-    fn process_diagnostics(&self, filename: &str, diagnostics: &[Diagnostic]) -> Fallible<()> {
+    fn process_diagnostics(&self, filename: &str) -> Fallible<()> {
         if !self.get(|state| state.text_documents.contains_key(filename))? {
             return Ok(());
         }
+        let (diagnostics, displays) = self.get(|state| {
+            (
+                state.diagnostics.get(filename).cloned().unwrap_or_default(),
+                state
+                    .diagnostics_displays
+                    .get(filename)
+                    .cloned()
+                    .unwrap_or_default(),
+            )
+        })?;
 
         let text = self.get(|state| {
             state
@@ -532,20 +751,9 @@ impl LanguageClient {
 
         // Line diagnostics.
         let mut line_diagnostics = HashMap::new();
-        for entry in diagnostics {
+        for entry in &diagnostics {
             let line = entry.range.start.line;
-            let mut msg = String::new();
-            if let Some(severity) = entry.severity {
-                msg += &format!("[{:?}]", severity);
-            }
-            if let Some(ref code) = entry.code {
-                let s = code.to_string();
-                if !s.is_empty() {
-                    msg += &format!("[{}]", s);
-                }
-            }
-            msg += &entry.message;
-            line_diagnostics.insert((filename.to_owned(), line), msg);
+            line_diagnostics.insert((filename.to_owned(), line), get_line_diagnostic(entry));
         }
         self.update(|state| {
             state
@@ -556,22 +764,14 @@ impl LanguageClient {
         })?;
 
         // Signs.
-        let mut signs: Vec<_> = diagnostics
+        let signed: Vec<_> = diagnostics
             .iter()
-            .map(|dn| {
-                let line = dn.range.start.line;
-                let text = lines
-                    .get(line as usize)
-                    .map(|l| l.to_string())
-                    .unwrap_or_default();
-
-                Sign::new(line + 1, text, dn.severity)
-            })
+            .zip(&displays)
+            .filter(|(_, displays)| displays.signs)
+            .map(|(dn, _)| dn)
             .collect();
-
-        // There might be multiple diagnostics for one line. Show only highest severity.
-        signs.sort_unstable();
-        signs.dedup();
+        let combine = self.get(|state| state.diagnosticsSignsCombine)?;
+        let mut signs = get_signs(&signed, &lines, combine);
         if let Some(diagnosticSignsMax) = self.get(|state| state.diagnosticsSignsMax)? {
             signs.truncate(diagnosticSignsMax as usize);
         }
@@ -584,32 +784,37 @@ impl LanguageClient {
         // Highlight.
         let diagnosticsDisplay = self.get(|state| state.diagnosticsDisplay.clone())?;
 
+        let diagnostics: Vec<_> = diagnostics
+            .iter()
+            .zip(&displays)
+            .filter(|(_, displays)| displays.highlights)
+            .map(|(dn, _)| dn)
+            .collect();
         let mut highlights = vec![];
-        for dn in diagnostics {
-            let line = dn.range.start.line;
-            let character_start = dn.range.start.character;
-            let character_end = dn.range.end.character;
-
+        for dn in &diagnostics {
             let severity = dn.severity.unwrap_or(DiagnosticSeverity::Hint);
             let group = diagnosticsDisplay
                 .get(&severity.to_int()?)
                 .ok_or_else(|| err_msg("Failed to get display"))?
                 .texthl
                 .clone();
-            // TODO: handle multi-line range.
-            let text = lines
-                .get(line as usize)
-                .and_then(|l| l.get((character_start as usize)..(character_end as usize)))
-                .map(ToOwned::to_owned)
-                .unwrap_or_default();
-
-            highlights.push(Highlight {
-                line,
-                character_start,
-                character_end,
-                group,
-                text,
-            });
+
+            // Highlights are placed line by line.
+            for (line, character_start, character_end) in split_range_by_line(&dn.range, &lines) {
+                let text = lines
+                    .get(line as usize)
+                    .and_then(|l| l.get((character_start as usize)..(character_end as usize)))
+                    .map(ToOwned::to_owned)
+                    .unwrap_or_default();
+
+                highlights.push(Highlight {
+                    line,
+                    character_start,
+                    character_end,
+                    group: group.clone(),
+                    text,
+                });
+            }
         }
         // dedup?
         self.update(|state| {
@@ -617,6 +822,26 @@ impl LanguageClient {
             Ok(())
         })?;
 
+        let windows: Vec<BufferWindow> = self
+            .vim()?
+            .rpcclient
+            .call("LSP#windows", json!([filename]))?;
+        if windows.is_empty() {
+            self.update(|state| {
+                state.diagnostics_pending.insert(filename.to_owned());
+                Ok(())
+            })?;
+            return Ok(());
+        }
+        self.update(|state| {
+            state.diagnostics_pending.remove(filename);
+            Ok(())
+        })?;
+        // Current buffer is updated on cursor moves.
+        if !windows.iter().any(|w| w.current) {
+            return self.render_diagnostics(filename, &windows);
+        }
+
         if !self.get(|state| state.is_nvim)? {
             // Clear old highlights.
             let ids = self.get(|state| state.highlight_match_ids.clone())?;
@@ -628,7 +853,7 @@ impl LanguageClient {
             // in a single call.
             let mut match_groups: HashMap<_, Vec<_>> = HashMap::new();
 
-            for dn in diagnostics {
+            for dn in &diagnostics {
                 let severity = dn
                     .severity
                     .unwrap_or(DiagnosticSeverity::Information)
@@ -692,44 +917,135 @@ impl LanguageClient {
         }
 
         if self.get(|state| state.use_virtual_text)? {
-            let namespace_id = self.get_or_create_namespace()?;
-
-            let (bufnr, viewport): (i64, Viewport) =
-                self.gather_args(&[VimVar::Bufnr, VimVar::Viewport], &json!({}))?;
+            let (bufnr, line, viewport): (i64, u64, Viewport) =
+                self.gather_args(&[VimVar::Bufnr, VimVar::Line, VimVar::Viewport], &json!({}))?;
             self.update(|state| {
                 state.viewport = viewport;
+                state.last_virtual_text_line = line;
                 Ok(())
             })?;
+            self.update_virtual_texts(filename, bufnr, Some(line), &[viewport])?;
+        }
 
-            let mut virtual_texts = vec![];
-            self.update(|state| {
-                if let Some(diag_list) = state.diagnostics.get(filename) {
-                    for diag in diag_list {
-                        if viewport.overlaps(diag.range) {
-                            virtual_texts.push(VirtualText {
-                                line: diag.range.start.line,
-                                text: diag.message.replace("\n", "  ").clone(),
-                                hl_group: state
-                                    .diagnosticsDisplay
-                                    .get(
-                                        &(diag.severity.unwrap_or(DiagnosticSeverity::Hint) as u64),
-                                    )
-                                    .ok_or_else(|| err_msg("Failed to get display"))?
-                                    .virtualTexthl
-                                    .clone(),
-                            });
-                        }
+        Ok(())
+    }
+
+    /// Show diagnostics of a file in windows of other buffers than current one.
+    /// In Vim, highlights are window-local matches and are not drawn here, only signs are.
+    fn render_diagnostics(&self, filename: &str, windows: &[BufferWindow]) -> Fallible<()> {
+        let bufnr = windows[0].bufnr;
+        let start = windows.iter().map(|w| w.viewport.start).min().unwrap_or(0);
+        let end = windows.iter().map(|w| w.viewport.end).max().unwrap_or(0);
+        let viewport = Viewport::new(start, end);
+
+        self.update_signs_and_highlights(filename, bufnr, &viewport)?;
+        if self.get(|state| state.use_virtual_text)? {
+            self.update_virtual_texts(filename, bufnr, None, &[viewport])?;
+        }
+        Ok(())
+    }
+
+    /// Place signs and highlights of diagnostics of a buffer within visible lines.
+    fn update_signs_and_highlights(
+        &self,
+        filename: &str,
+        bufnr: i64,
+        viewport: &Viewport,
+    ) -> Fallible<()> {
+        let signs: Vec<_> = self.update(|state| {
+            Ok(state
+                .signs
+                .entry(filename.to_owned())
+                .or_insert_with(|| vec![])
+                .iter()
+                .filter_map(|s| {
+                    if s.line < viewport.start + 1 || s.line > viewport.end {
+                        return None;
                     }
+
+                    Some(s.clone())
+                })
+                .collect())
+        })?;
+        let signs_prev = self.get(|state| {
+            state
+                .signs_placed
+                .get(filename)
+                .cloned()
+                .unwrap_or_default()
+        })?;
+        if signs != signs_prev {
+            let (priority, mut next_id) = self.get(|state| {
+                if state.sign_groups {
+                    let next_id = state.sign_ids.get(filename).cloned().unwrap_or(1);
+                    (Some(state.diagnosticsSignsPriority), next_id)
+                } else {
+                    // Without sign groups, keep clear of ids used by other plugins.
+                    let next_id = state.sign_ids.get(filename).cloned().unwrap_or(75_000);
+                    (None, next_id)
                 }
+            })?;
+            let (signs, cmds) =
+                get_command_update_signs(&signs_prev, &signs, filename, priority, &mut next_id);
+            self.update(|state| {
+                state.signs_placed.insert(filename.to_owned(), signs);
+                state.sign_ids.insert(filename.to_owned(), next_id);
                 Ok(())
             })?;
-            self.vim()?.set_virtual_texts(
-                bufnr,
-                namespace_id,
-                viewport.start,
-                viewport.end,
-                &virtual_texts,
+
+            info!("Updating signs: {:?}", cmds);
+            self.vim()?.command(&cmds)?;
+        }
+
+        let highlights: Vec<_> = self.update(|state| {
+            Ok(state
+                .highlights
+                .entry(filename.to_owned())
+                .or_insert_with(|| vec![])
+                .iter()
+                .filter_map(|h| {
+                    if h.line < viewport.start || h.line >= viewport.end {
+                        return None;
+                    }
+
+                    Some(h.clone())
+                })
+                .collect())
+        })?;
+
+        if Some(highlights.clone())
+            != self.get(|state| state.highlights_placed.get(filename).cloned())?
+            && self.get(|state| state.is_nvim)?
+        {
+            let source = if let Some(source) = self.get(|state| state.highlight_source)? {
+                source
+            } else {
+                let source = self
+                    .vim()?
+                    .rpcclient
+                    .call("nvim_buf_add_highlight", json!([0, 0, "Error", 1, 1, 1]))?;
+                self.update(|state| {
+                    state.highlight_source = Some(source);
+                    Ok(())
+                })?;
+                source
+            };
+
+            self.update(|state| {
+                state
+                    .highlights_placed
+                    .insert(filename.to_owned(), highlights.clone());
+                Ok(())
+            })?;
+
+            self.vim()?.rpcclient.notify(
+                "nvim_buf_clear_highlight",
+                json!([bufnr, source, viewport.start, viewport.end]),
             )?;
+
+            self.vim()?
+                .rpcclient
+                .notify("s:AddHighlights", json!([source, highlights, bufnr]))?;
         }
 
         Ok(())
@@ -899,68 +1215,571 @@ impl LanguageClient {
         let mut text = texts.pop().unwrap_or_default();
 
         if text.is_empty() {
-            let reader = BufReader::new(File::open(path)?);
-            text = reader
-                .lines()
+            text = self
+                .read_document_lines(&path.as_ref().to_string_lossy())?
+                .into_iter()
                 .nth(line.to_usize()?)
-                .ok_or_else(|| format_err!("Failed to get line! line: {}", line))??;
+                .ok_or_else(|| format_err!("Failed to get line! line: {}", line))?;
         }
 
         Ok(text.trim().into())
     }
 
-    fn try_handle_command_by_client(&self, cmd: &Command) -> Fallible<bool> {
-        if !CommandsClient.contains(&cmd.command.as_str()) {
-            return Ok(false);
+    /// Edit a file, or the virtual document of a URI that is not a file.
+    fn edit(&self, goto_cmd: &Option<String>, path: impl AsRef<Path>) -> Fallible<()> {
+        let path = path.as_ref().to_string_lossy().into_owned();
+        if !is_virtual_document(&path) {
+            return self.vim()?.edit(goto_cmd, &path);
         }
 
-        if cmd.command == "java.apply.workspaceEdit" {
-            if let Some(ref edits) = cmd.arguments {
-                for edit in edits {
-                    let edit: WorkspaceEdit = serde_json::from_value(edit.clone())?;
-                    self.apply_WorkspaceEdit(&edit, &Value::Null)?;
-                }
-            }
-        } else {
-            bail!("Not implemented: {}", cmd.command);
+        // Content is fetched from the server of the document we're navigating from.
+        let languageId: String = self.vim()?.eval(VimVar::LanguageId)?;
+        self.vim()?.edit(goto_cmd, &path)?;
+        self.load_virtual_document(&languageId, &path)
+    }
+
+    /// Fill current buffer with the content of a virtual document. Once the buffer has its
+    /// filetype set, it is opened with the server like any other document.
+    fn load_virtual_document(&self, languageId: &str, uri: &str) -> Fallible<()> {
+        if self.get(|state| state.text_documents.contains_key(uri))? {
+            return Ok(());
         }
 
-        Ok(true)
-    }
+        let scheme = uri.split(':').next().unwrap_or_default();
+        let method = self
+            .get(|state| state.virtualDocumentMethods.get(scheme).cloned())?
+            .ok_or_else(|| format_err!("Don't know how to get content of {}", uri))?;
+        info!("Begin load virtual document: {}", uri);
+
+        let result: Value = self.get_client(&Some(languageId.to_owned()))?.call(
+            &method,
+            json!({
+                "uri": uri,
+                "textDocument": { "uri": uri },
+            }),
+        )?;
+        // Servers return the content either as is, or in a field of an object.
+        let content = match result {
+            Value::String(content) => content,
+            Value::Object(ref object) => ["source", "text", "content"]
+                .iter()
+                .filter_map(|key| object.get(*key).and_then(Value::as_str))
+                .next()
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| format_err!("Unexpected content: {:?}", result))?,
+            _ => bail!("Unexpected content: {:?}", result),
+        };
+        let lines: Vec<String> = content.lines().map(ToOwned::to_owned).collect();
 
-    fn cleanup(&self, languageId: &str) -> Fallible<()> {
-        info!("Begin cleanup");
+        self.vim()?.command(vec![
+            "setlocal buftype=nofile bufhidden=hide noswapfile modifiable noreadonly",
+            "silent %delete _",
+        ])?;
+        self.vim()?.setline(1, &lines)?;
+        self.vim()?.command(vec![
+            "setlocal nomodifiable readonly".to_owned(),
+            "let b:LanguageClient_virtualDocument = 1".to_owned(),
+            format!("setlocal filetype={}", languageId),
+        ])?;
 
-        let root = self.get(|state| {
+        info!("End load virtual document: {}", uri);
+        Ok(())
+    }
+
+    fn get_position_encoding(&self, languageId: &str) -> Fallible<PositionEncoding> {
+        self.get(|state| {
             state
-                .roots
+                .position_encodings
                 .get(languageId)
                 .cloned()
-                .ok_or_else(|| format_err!("No project root found! languageId: {}", languageId))
-        })??;
+                .unwrap_or_default()
+        })
+    }
 
-        let mut filenames = vec![];
-        self.get(|state| {
-            for f in state.diagnostics.keys() {
-                if f.starts_with(&root) {
-                    filenames.push(f.clone());
-                }
-            }
+    /// Get the lines of a file as the language server sees them, i.e., the synced text of open
+    /// documents, and the content on disk otherwise.
+    fn get_document_lines(&self, filename: &str) -> Fallible<Vec<String>> {
+        let (text, format) = self.get(|state| {
+            (
+                state
+                    .text_documents
+                    .get(filename)
+                    .map(|document| document.text.clone()),
+                state
+                    .document_formats
+                    .get(filename)
+                    .cloned()
+                    .unwrap_or_default(),
+            )
         })?;
-        for f in filenames {
-            self.process_diagnostics(&f, &[])?;
+        match text {
+            Some(text) => Ok(format.split(&text).0),
+            None => Ok(self.read_document_lines(filename).unwrap_or_default()),
         }
-        self.languageClient_handleCursorMoved(&Value::Null)?;
+    }
 
-        self.update(|state| {
-            state.diagnostics.retain(|f, _| !f.starts_with(&root));
-            state.clients.remove(&Some(languageId.into()));
+    /// Read the lines of a file on disk, decoded and split according to its recorded format.
+    fn read_document_lines(&self, filename: &str) -> Fallible<Vec<String>> {
+        let format = self.get(|state| {
+            state
+                .document_formats
+                .get(filename)
+                .cloned()
+                .unwrap_or_default()
+        })?;
+        let text = decode_text(&std::fs::read(filename)?, &format.encoding);
+        Ok(format.split(&text).0)
+    }
+
+    /// Convert an editor position, i.e., 0-based line and byte column, into an LSP position.
+    fn to_lsp_position(
+        &self,
+        languageId: &str,
+        filename: &str,
+        line: u64,
+        col: u64,
+    ) -> Fallible<Position> {
+        let encoding = self.get_position_encoding(languageId)?;
+        if encoding == PositionEncoding::Utf8 {
+            return Ok(Position::new(line, col));
+        }
+
+        let lines = self.get_document_lines(filename)?;
+        let character = lines
+            .get(line.to_usize()?)
+            .map_or(col, |text| byte_to_character(text, col, encoding));
+        Ok(Position::new(line, character))
+    }
+
+    /// Convert the columns of locations returned by a language server into editor columns.
+    fn to_editor_locations(
+        &self,
+        languageId: &str,
+        locations: Vec<Location>,
+    ) -> Fallible<Vec<Location>> {
+        let mut converters = HashMap::new();
+        let mut result = vec![];
+        for mut loc in locations {
+            let filename = loc.uri.filepath()?.to_string_lossy().into_owned();
+            if !converters.contains_key(&filename) {
+                let to_column = self.get_column_converter(languageId, &filename)?;
+                converters.insert(filename.clone(), to_column);
+            }
+            let to_column = &converters[&filename];
+            loc.range.start.character = to_column(&loc.range.start);
+            loc.range.end.character = to_column(&loc.range.end);
+            result.push(loc);
+        }
+        Ok(result)
+    }
+
+    /// Same as `to_editor_locations`, for the locations of symbols.
+    fn to_editor_symbols(
+        &self,
+        languageId: &str,
+        symbols: Vec<SymbolInformation>,
+    ) -> Fallible<Vec<SymbolInformation>> {
+        let locations = symbols.iter().map(|sym| sym.location.clone()).collect();
+        let locations = self.to_editor_locations(languageId, locations)?;
+        Ok(symbols
+            .into_iter()
+            .zip(locations)
+            .map(|(sym, location)| SymbolInformation { location, ..sym })
+            .collect())
+    }
+
+    /// Get diagnostics baseline of a project, reading it from file when first used.
+    fn get_diagnostics_baseline(&self, root: &str) -> Fallible<DiagnosticsBaseline> {
+        if let Some(baseline) = self.get(|state| state.diagnostics_baselines.get(root).cloned())? {
+            return Ok(baseline);
+        }
+
+        let path = Path::new(root).join(&self.get(|state| state.diagnosticsBaselinePath.clone())?);
+        let baseline = DiagnosticsBaseline::read(&path)?;
+        self.update(|state| {
+            state
+                .diagnostics_baselines
+                .insert(root.to_owned(), baseline.clone());
+            Ok(())
+        })?;
+        Ok(baseline)
+    }
+
+    /// Get root of the project containing file, preferring the project of given server.
+    fn get_file_root(&self, languageId: Option<&str>, filename: &str) -> Fallible<Option<String>> {
+        self.get(|state| {
+            languageId
+                .and_then(|languageId| state.roots.get(languageId))
+                .filter(|root| filename.starts_with(root.as_str()))
+                .or_else(|| {
+                    state
+                        .roots
+                        .values()
+                        .filter(|root| filename.starts_with(root.as_str()))
+                        .max_by_key(|root| root.len())
+                })
+                .cloned()
+        })
+    }
+
+    /// Merge diagnostics of a file published by all servers, and store them for display.
+    ///
+    /// Diagnostics of each server are converted into editor columns and get their displays by
+    /// the settings of that server. Diagnostics in the baseline of the project are left out.
+    fn merge_diagnostics(&self, filename: &str) -> Fallible<Vec<Diagnostic>> {
+        let languageIds: Vec<String> = self.get(|state| {
+            let mut languageIds: Vec<_> = state
+                .server_diagnostics
+                .iter()
+                .filter(|(_, diagnostics)| diagnostics.contains_key(filename))
+                .map(|(languageId, _)| languageId.clone())
+                .collect();
+            languageIds.sort();
+            languageIds
+        })?;
+
+        let mut diagnostics = vec![];
+        for languageId in &languageIds {
+            let filter = self.get_diagnostics_filter(languageId)?;
+            let to_column = self.get_column_converter(languageId, filename)?;
+            let server_diagnostics =
+                self.get(|state| state.server_diagnostics[languageId][filename].clone())?;
+            for mut dn in server_diagnostics {
+                dn.range.start.character = to_column(&dn.range.start);
+                dn.range.end.character = to_column(&dn.range.end);
+                if let Some(infos) = dn.related_information.take() {
+                    let locations = infos.iter().map(|info| info.location.clone()).collect();
+                    let locations = self.to_editor_locations(languageId, locations)?;
+                    dn.related_information = Some(
+                        infos
+                            .into_iter()
+                            .zip(locations)
+                            .map(|(info, location)| DiagnosticRelatedInformation {
+                                location,
+                                ..info
+                            })
+                            .collect(),
+                    );
+                }
+                diagnostics.push((filter.displays(&dn), dn));
+            }
+        }
+
+        let root = self.get_file_root(languageIds.first().map(String::as_str), filename)?;
+        if let Some(root) = root {
+            let baseline = self.get_diagnostics_baseline(&root).unwrap_or_else(|err| {
+                warn!("Failed to load diagnostics baseline: {}", err);
+                DiagnosticsBaseline::default()
+            });
+            let relpath = get_relative_path(filename, &root);
+            let dns: Vec<_> = diagnostics.iter().map(|(_, dn)| dn.clone()).collect();
+            let suppressed = baseline.suppressed(&relpath, &dns);
+            diagnostics = diagnostics
+                .into_iter()
+                .zip(suppressed)
+                .filter(|(_, suppressed)| !suppressed)
+                .map(|(diagnostic, _)| diagnostic)
+                .collect();
+        }
+        diagnostics.sort_by_key(
+            // First sort by line.
+            // Then severity descendingly. Error should come last since when processing item
+            // comes later will override its precedance.
+            // Then by character descendingly.
+            |(_, diagnostic)| {
+                (
+                    diagnostic.range.start.line,
+                    -(diagnostic.severity.unwrap_or(DiagnosticSeverity::Hint) as i8),
+                    -(diagnostic.range.start.line as i64),
+                )
+            },
+        );
+        let (displays, diagnostics): (Vec<_>, Vec<_>) = diagnostics.into_iter().unzip();
+
+        self.update(|state| {
+            if languageIds.is_empty() {
+                state.diagnostics.remove(filename);
+                state.diagnostics_displays.remove(filename);
+            } else {
+                state
+                    .diagnostics
+                    .insert(filename.to_owned(), diagnostics.clone());
+                state
+                    .diagnostics_displays
+                    .insert(filename.to_owned(), displays);
+            }
+            Ok(diagnostics)
+        })
+    }
+
+    /// Set virtual texts of diagnostics of a buffer within viewports. With
+    /// `g:LanguageClient_virtualTextCurrentLineOnly`, only the one of cursor line is set, if any.
+    fn update_virtual_texts(
+        &self,
+        filename: &str,
+        bufnr: i64,
+        line: Option<u64>,
+        viewports: &[Viewport],
+    ) -> Fallible<()> {
+        let namespace_id = self.get_or_create_namespace()?;
+        let (
+            diagnostics,
+            displays,
+            diagnosticsDisplay,
+            format,
+            highestSeverityOnly,
+            currentLineOnly,
+        ) = self.get(|state| {
+            (
+                state.diagnostics.get(filename).cloned().unwrap_or_default(),
+                state
+                    .diagnostics_displays
+                    .get(filename)
+                    .cloned()
+                    .unwrap_or_default(),
+                state.diagnosticsDisplay.clone(),
+                VirtualTextFormat {
+                    prefix: state.virtualTextPrefix.clone(),
+                    template: state.virtualTextFormat.clone(),
+                    maxLength: state.virtualTextMaxLength,
+                },
+                state.virtualTextHighestSeverityOnly,
+                state.virtualTextCurrentLineOnly,
+            )
+        })?;
+
+        for viewport in viewports {
+            let mut diagnostics: Vec<_> = diagnostics
+                .iter()
+                .zip(&displays)
+                .filter(|(dn, displays)| {
+                    viewport.overlaps(dn.range)
+                        && displays.virtualText
+                        && (!currentLineOnly || Some(dn.range.start.line) == line)
+                })
+                .map(|(dn, _)| dn)
+                .collect();
+            if highestSeverityOnly {
+                let mut highest: HashMap<u64, &Diagnostic> = HashMap::new();
+                for dn in diagnostics {
+                    let severity = dn.severity.unwrap_or(DiagnosticSeverity::Hint) as u64;
+                    let entry = highest.entry(dn.range.start.line).or_insert(dn);
+                    if severity <= entry.severity.unwrap_or(DiagnosticSeverity::Hint) as u64 {
+                        *entry = dn;
+                    }
+                }
+                diagnostics = highest.into_iter().map(|(_, dn)| dn).collect();
+                diagnostics.sort_by_key(|dn| dn.range.start.line);
+            }
+
+            let mut virtual_texts = vec![];
+            for dn in diagnostics {
+                let display = diagnosticsDisplay
+                    .get(&(dn.severity.unwrap_or(DiagnosticSeverity::Hint) as u64))
+                    .ok_or_else(|| err_msg("Failed to get display"))?;
+                virtual_texts.push(VirtualText {
+                    line: dn.range.start.line,
+                    text: format.apply(dn, &display.name),
+                    hl_group: display.virtualTexthl.clone(),
+                });
+            }
+            self.vim()?.set_virtual_texts(
+                bufnr,
+                namespace_id,
+                viewport.start,
+                viewport.end,
+                &virtual_texts,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Publish state of servers and diagnostic counts, per file and per project, to
+    /// `g:LanguageClient_status`, so statusline can read them without calling into us.
+    fn update_status(&self) -> Fallible<()> {
+        let status = self.get(|state| {
+            let files: HashMap<_, _> = state
+                .diagnostics
+                .iter()
+                .map(|(filename, diagnostics)| {
+                    (filename.clone(), DiagnosticsCounts::new(diagnostics))
+                })
+                .collect();
+
+            let mut servers = serde_json::Map::new();
+            for (languageId, server_state) in &state.server_states {
+                let root = state.roots.get(languageId).cloned().unwrap_or_default();
+                let mut counts = DiagnosticsCounts::default();
+                if !root.is_empty() {
+                    for (_, file_counts) in files.iter().filter(|(f, _)| f.starts_with(&root)) {
+                        counts.add(file_counts);
+                    }
+                }
+                let mut progress: Vec<_> = state
+                    .server_progress
+                    .get(languageId)
+                    .map(|progress| progress.values().cloned().collect())
+                    .unwrap_or_default();
+                progress.sort();
+
+                servers.insert(
+                    languageId.clone(),
+                    json!({
+                        "state": server_state,
+                        "root": root,
+                        "progress": progress,
+                        "diagnostics": counts,
+                    }),
+                );
+            }
+
+            json!({
+                "servers": servers,
+                "diagnostics": files,
+            })
+        })?;
+        self.vim()?.rpcclient.notify("s:SetStatus", json!([status]))
+    }
+
+    /// Get the diagnostics filter of a server.
+    fn get_diagnostics_filter(&self, languageId: &str) -> Fallible<DiagnosticsFilter> {
+        self.get(|state| {
+            state
+                .diagnosticsFilters
+                .get(languageId)
+                .or_else(|| state.diagnosticsFilters.get("*"))
+                .cloned()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Get a function that converts LSP positions in a file into 0-based editor (byte) columns.
+    fn get_column_converter(
+        &self,
+        languageId: &str,
+        filename: &str,
+    ) -> Fallible<impl Fn(&Position) -> u64> {
+        let encoding = self.get_position_encoding(languageId)?;
+        let lines = if encoding == PositionEncoding::Utf8 {
+            vec![]
+        } else {
+            self.get_document_lines(filename)?
+        };
+        Ok(move |position: &Position| {
+            lines
+                .get(position.line as usize)
+                .map_or(position.character, |text| {
+                    character_to_byte(text, position.character, encoding)
+                })
+        })
+    }
+
+    fn try_handle_command_by_client(&self, cmd: &Command) -> Fallible<bool> {
+        if !CommandsClient.contains(&cmd.command.as_str()) {
+            return Ok(false);
+        }
+
+        if cmd.command == "java.apply.workspaceEdit" {
+            if let Some(ref edits) = cmd.arguments {
+                for edit in edits {
+                    let edit: WorkspaceEdit = serde_json::from_value(edit.clone())?;
+                    self.apply_WorkspaceEdit(&edit, &Value::Null)?;
+                }
+            }
+        } else {
+            bail!("Not implemented: {}", cmd.command);
+        }
+
+        Ok(true)
+    }
+
+    /// Get how documents should be synced to the language server, as negotiated in its
+    /// `textDocumentSync` capability. A bare sync kind implies open/close and save notifications.
+    fn get_text_document_sync_options(
+        &self,
+        languageId: &str,
+    ) -> Fallible<TextDocumentSyncOptions> {
+        let sync = self.get(|state| {
+            state
+                .capabilities
+                .get(languageId)
+                .map(|result| result["capabilities"]["textDocumentSync"].clone())
+                .unwrap_or_default()
+        })?;
+        let sync = if sync.is_null() {
+            TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)
+        } else {
+            serde_json::from_value(sync)?
+        };
+
+        let options = match sync {
+            TextDocumentSyncCapability::Kind(kind) => TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(kind),
+                save: Some(SaveOptions::default()),
+                ..TextDocumentSyncOptions::default()
+            },
+            TextDocumentSyncCapability::Options(options) => options,
+        };
+        Ok(options)
+    }
+
+    /// Execute a command, either by the client itself or by the language server.
+    fn execute_command(&self, cmd: &Command) -> Fallible<()> {
+        if self.try_handle_command_by_client(cmd)? {
+            return Ok(());
+        }
+
+        self.workspace_executeCommand(&json!({
+            "command": cmd.command,
+            "arguments": cmd.arguments,
+        }))?;
+        Ok(())
+    }
+
+    fn cleanup(&self, languageId: &str) -> Fallible<()> {
+        info!("Begin cleanup");
+
+        let root = self.get(|state| {
+            state
+                .roots
+                .get(languageId)
+                .cloned()
+                .ok_or_else(|| format_err!("No project root found! languageId: {}", languageId))
+        })??;
+
+        // Diagnostics of other servers are kept.
+        let filenames: Vec<_> = self.update(|state| {
+            Ok(state
+                .server_diagnostics
+                .remove(languageId)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(filename, _)| filename)
+                .collect())
+        })?;
+        for f in filenames {
+            self.merge_diagnostics(&f)?;
+            self.process_diagnostics(&f)?;
+        }
+        self.languageClient_handleCursorMoved(&Value::Null)?;
+
+        self.update(|state| {
+            state.clients.remove(&Some(languageId.into()));
+            state.server_states.remove(languageId);
+            state.server_progress.remove(languageId);
+            state.diagnostics_baselines.remove(&root);
+            state.diagnostics_result_ids.remove(languageId);
+            state.code_descriptions.remove(languageId);
+            state.workspace_diagnostics_pulls.remove(languageId);
             state.last_cursor_line = 0;
             state.text_documents.retain(|f, _| !f.starts_with(&root));
             state.roots.remove(languageId);
             Ok(())
         })?;
         self.update_quickfixlist()?;
+        self.update_status()?;
 
         self.vim()?.command(vec![
             format!("let {}=0", VIM__ServerStatus),
@@ -1004,6 +1823,19 @@ impl LanguageClient {
         Ok(())
     }
 
+    /// Show a location in the preview window, with the cursor staying in current window.
+    fn peek(&self, location: &Location) -> Fallible<()> {
+        let path = location.uri.filepath()?.to_string_lossy().into_owned();
+        self.vim()?.rpcclient.notify(
+            "s:Peek",
+            json!([
+                path,
+                location.range.start.line + 1,
+                location.range.start.character + 1
+            ]),
+        )
+    }
+
     /////// LSP ///////
 
     fn initialize(&self, params: &Value) -> Fallible<Value> {
@@ -1030,52 +1862,41 @@ impl LanguageClient {
         };
 
         let trace = self.get(|state| state.trace.clone())?;
+        let capabilities = client_capabilities(&CapabilitiesOptions {
+            has_snippet_support,
+        });
 
         let result: Value = self.get_client(&Some(languageId.clone()))?.call(
             lsp::request::Initialize::METHOD,
-            InitializeParams {
+            serde_json::to_value(InitializeParams {
                 process_id: Some(u64::from(std::process::id())),
                 root_path: Some(root.clone()),
                 root_uri: Some(root.to_url()?),
                 initialization_options,
-                capabilities: ClientCapabilities {
-                    text_document: Some(TextDocumentClientCapabilities {
-                        completion: Some(CompletionCapability {
-                            completion_item: Some(CompletionItemCapability {
-                                snippet_support: Some(has_snippet_support),
-                                ..CompletionItemCapability::default()
-                            }),
-                            ..CompletionCapability::default()
-                        }),
-                        signature_help: Some(SignatureHelpCapability {
-                            signature_information: Some(SignatureInformationSettings {
-                                documentation_format: None,
-                                parameter_information: Some(ParameterInformationSettings {
-                                    label_offset_support: Some(true),
-                                }),
-                            }),
-                            ..SignatureHelpCapability::default()
-                        }),
-                        ..TextDocumentClientCapabilities::default()
-                    }),
-                    workspace: Some(WorkspaceClientCapabilities {
-                        apply_edit: Some(true),
-                        did_change_watched_files: Some(GenericCapability {
-                            dynamic_registration: Some(true),
-                        }),
-                        ..WorkspaceClientCapabilities::default()
-                    }),
-                    ..ClientCapabilities::default()
-                },
+                capabilities: ClientCapabilities::default(),
                 trace,
                 workspace_folders: None,
-            },
+            })?
+            .combine(&json!({ "capabilities": capabilities })),
         )?;
 
+        let position_encoding = [
+            &result["capabilities"]["positionEncoding"],
+            &result["offsetEncoding"],
+        ]
+        .iter()
+        .filter_map(|encoding| serde_json::from_value((*encoding).clone()).ok())
+        .next()
+        .unwrap_or_default();
+        info!("Position encoding: {:?}", position_encoding);
+
         self.update(|state| {
             state
                 .capabilities
                 .insert(languageId.clone(), result.clone());
+            state
+                .position_encodings
+                .insert(languageId.clone(), position_encoding);
             Ok(())
         })?;
 
@@ -1108,16 +1929,9 @@ impl LanguageClient {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::HoverRequest::METHOD);
         let (languageId, filename, line, character, handle): (String, String, u64, u64, bool) =
-            self.gather_args(
-                &[
-                    VimVar::LanguageId,
-                    VimVar::Filename,
-                    VimVar::Line,
-                    VimVar::Character,
-                    VimVar::Handle,
-                ],
-                params,
-            )?;
+            self.gather_args(POSITION_ARGS, params)?;
+
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
 
         let result = self.get_client(&Some(languageId))?.call(
             lsp::request::HoverRequest::METHOD,
@@ -1125,7 +1939,7 @@ impl LanguageClient {
                 text_document: TextDocumentIdentifier {
                     uri: filename.to_url()?,
                 },
-                position: Position { line, character },
+                position,
             },
         )?;
 
@@ -1157,7 +1971,7 @@ impl LanguageClient {
         self.textDocument_didChange(params)?;
         let (method,): (String,) = self.gather_args(&["method"], params)?;
         info!("Begin {}", method);
-        let (languageId, filename, word, line, character, handle, goto_cmd): (
+        let (languageId, filename, word, line, character, handle, goto_cmd, peek): (
             String,
             String,
             String,
@@ -1165,69 +1979,58 @@ impl LanguageClient {
             u64,
             bool,
             Option<String>,
-        ) = self.gather_args(
-            &[
-                VimVar::LanguageId,
-                VimVar::Filename,
-                VimVar::Cword,
-                VimVar::Line,
-                VimVar::Character,
-                VimVar::Handle,
-                VimVar::GotoCmd,
-            ],
-            params,
-        )?;
+            u8,
+        ) = self.gather_args(FIND_LOCATIONS_ARGS, params)?;
+
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
 
+        // Prefetched values, e.g., the text of the document, are not for the server.
         let params = serde_json::to_value(TextDocumentPositionParams {
             text_document: TextDocumentIdentifier {
                 uri: filename.to_url()?,
             },
-            position: Position { line, character },
+            position,
         })?
-        .combine(params);
+        .combine(&without_prefetched(params));
 
-        let result = self.get_client(&Some(languageId))?.call(&method, &params)?;
+        let result = self
+            .get_client(&Some(languageId.clone()))?
+            .call(&method, &params)?;
 
         if !handle {
             return Ok(result);
         }
 
         let response: Option<GotoDefinitionResponse> = result.clone().to_lsp()?;
-
-        match response {
-            None => {
+        let locations = response.map(get_locations).unwrap_or_default();
+        let locations = self.to_editor_locations(&languageId, locations)?;
+        // Virtual documents are loaded into current buffer, so they are jumped to.
+        let peek = peek == 1
+            && locations.len() == 1
+            && !is_virtual_document(&locations[0].uri.filepath()?.to_string_lossy());
+
+        match locations.len() {
+            0 => {
                 self.vim()?.echowarn("Not found!")?;
                 return Ok(Value::Null);
             }
-            Some(GotoDefinitionResponse::Scalar(loc)) => {
-                self.vim()?.edit(&goto_cmd, loc.uri.filepath()?)?;
+            1 if peek => self.peek(&locations[0])?,
+            1 => {
+                let loc = &locations[0];
+                self.edit(&goto_cmd, loc.uri.filepath()?)?;
                 self.vim()?
                     .cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
+                let cur_file: String = self.vim()?.eval("expand('%')")?;
+                self.vim()?.echomsg_ellipsis(format!(
+                    "{} {}:{}",
+                    cur_file,
+                    loc.range.start.line + 1,
+                    loc.range.start.character + 1
+                ))?;
             }
-            Some(GotoDefinitionResponse::Array(arr)) => match arr.len() {
-                0 => self.vim()?.echowarn("Not found!")?,
-                1 => {
-                    let loc = arr.get(0).ok_or_else(|| err_msg("Not found!"))?;
-                    self.vim()?.edit(&goto_cmd, loc.uri.filepath()?)?;
-                    self.vim()?
-                        .cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
-                    let cur_file: String = self.vim()?.eval("expand('%')")?;
-                    self.vim()?.echomsg_ellipsis(format!(
-                        "{} {}:{}",
-                        cur_file,
-                        loc.range.start.line + 1,
-                        loc.range.start.character + 1
-                    ))?;
-                }
-                _ => {
-                    let title = format!("[LC]: search for {}", word);
-                    self.display_locations(&arr, &title)?
-                }
-            },
-            Some(GotoDefinitionResponse::Link(_)) => {
-                self.vim()?
-                    .echowarn("Definition links are not supported!")?;
-                return Ok(Value::Null);
+            _ => {
+                let title = format!("[LC]: search for {}", word);
+                self.display_locations(&locations, &title)?
             }
         };
 
@@ -1246,18 +2049,7 @@ impl LanguageClient {
             String,
             Option<String>,
             bool,
-        ) = self.gather_args(
-            &[
-                VimVar::LanguageId,
-                VimVar::Filename,
-                VimVar::Line,
-                VimVar::Character,
-                VimVar::Cword,
-                VimVar::NewName,
-                VimVar::Handle,
-            ],
-            params,
-        )?;
+        ) = self.gather_args(RENAME_ARGS, params)?;
 
         let mut new_name = new_name.unwrap_or_default();
         if new_name.is_empty() {
@@ -1271,13 +2063,15 @@ impl LanguageClient {
             return Ok(Value::Null);
         }
 
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
+
         let result = self.get_client(&Some(languageId))?.call(
             lsp::request::Rename::METHOD,
             RenameParams {
                 text_document: TextDocumentIdentifier {
                     uri: filename.to_url()?,
                 },
-                position: Position { line, character },
+                position,
                 new_name,
             },
         )?;
@@ -1297,12 +2091,10 @@ impl LanguageClient {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::DocumentSymbolRequest::METHOD);
 
-        let (languageId, filename, handle): (String, String, bool) = self.gather_args(
-            &[VimVar::LanguageId, VimVar::Filename, VimVar::Handle],
-            params,
-        )?;
+        let (languageId, filename, handle): (String, String, bool) =
+            self.gather_args(DOCUMENT_ARGS, params)?;
 
-        let result = self.get_client(&Some(languageId))?.call(
+        let result = self.get_client(&Some(languageId.clone()))?.call(
             lsp::request::DocumentSymbolRequest::METHOD,
             DocumentSymbolParams {
                 text_document: TextDocumentIdentifier {
@@ -1315,7 +2107,13 @@ impl LanguageClient {
             return Ok(result);
         }
 
-        let symbols: Vec<SymbolInformation> = serde_json::from_value(result.clone())?;
+        let symbols = match serde_json::from_value(result.clone())? {
+            DocumentSymbolResponse::Flat(symbols) => symbols,
+            DocumentSymbolResponse::Nested(symbols) => {
+                flatten_document_symbols(&filename.to_url()?, &symbols, None)
+            }
+        };
+        let symbols = self.to_editor_symbols(&languageId, symbols)?;
         let title = format!("[LC]: symbols for {}", filename);
 
         let selectionUI = self.get(|state| state.selectionUI)?;
@@ -1371,25 +2169,18 @@ impl LanguageClient {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::CodeActionRequest::METHOD);
         let (languageId, filename, line, character, handle): (String, String, u64, u64, bool) =
-            self.gather_args(
-                &[
-                    VimVar::LanguageId,
-                    VimVar::Filename,
-                    VimVar::Line,
-                    VimVar::Character,
-                    VimVar::Handle,
-                ],
-                params,
-            )?;
+            self.gather_args(POSITION_ARGS, params)?;
 
-        let position = lsp::Position::new(line, character);
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
         // Unify filename.
         let filename = filename.canonicalize();
 
+        // Diagnostics as published by this server, in its position encoding.
         let diagnostics: Vec<_> = self.get(|state| {
             state
-                .diagnostics
-                .get(&filename)
+                .server_diagnostics
+                .get(&languageId)
+                .and_then(|diagnostics| diagnostics.get(&filename))
                 .unwrap_or(&vec![])
                 .iter()
                 .filter(|dn| position >= dn.range.start && position < dn.range.end)
@@ -1404,8 +2195,8 @@ impl LanguageClient {
                     uri: filename.to_url()?,
                 },
                 range: Range {
-                    start: Position { line, character },
-                    end: Position { line, character },
+                    start: position,
+                    end: position,
                 },
                 context: CodeActionContext {
                     diagnostics,
@@ -1414,15 +2205,15 @@ impl LanguageClient {
             },
         )?;
 
-        let commands: Vec<Command> = serde_json::from_value(result.clone())?;
+        let actions: Vec<CodeActionOrCommand> = serde_json::from_value(result.clone())?;
 
-        let source: Vec<_> = commands
+        let source: Vec<_> = actions
             .iter()
-            .map(|cmd| format!("{}: {}", cmd.command, cmd.title))
+            .map(CodeActionOrCommand::to_selection_entry)
             .collect();
 
         self.update(|state| {
-            state.stashed_codeAction_commands = commands;
+            state.stashed_codeAction_actions = actions;
             Ok(())
         })?;
 
@@ -1443,16 +2234,9 @@ impl LanguageClient {
         info!("Begin {}", lsp::request::Completion::METHOD);
 
         let (languageId, filename, line, character, handle): (String, String, u64, u64, bool) =
-            self.gather_args(
-                &[
-                    VimVar::LanguageId,
-                    VimVar::Filename,
-                    VimVar::Line,
-                    VimVar::Character,
-                    VimVar::Handle,
-                ],
-                params,
-            )?;
+            self.gather_args(POSITION_ARGS, params)?;
+
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
 
         let result = self.get_client(&Some(languageId))?.call(
             lsp::request::Completion::METHOD,
@@ -1460,7 +2244,7 @@ impl LanguageClient {
                 text_document: TextDocumentIdentifier {
                     uri: filename.to_url()?,
                 },
-                position: Position { line, character },
+                position,
             },
         )?;
 
@@ -1476,16 +2260,9 @@ impl LanguageClient {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::SignatureHelpRequest::METHOD);
         let (languageId, filename, line, character, handle): (String, String, u64, u64, bool) =
-            self.gather_args(
-                &[
-                    VimVar::LanguageId,
-                    VimVar::Filename,
-                    VimVar::Line,
-                    VimVar::Character,
-                    VimVar::Handle,
-                ],
-                params,
-            )?;
+            self.gather_args(POSITION_ARGS, params)?;
+
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
 
         let result = self.get_client(&Some(languageId))?.call(
             lsp::request::SignatureHelpRequest::METHOD,
@@ -1493,7 +2270,7 @@ impl LanguageClient {
                 text_document: TextDocumentIdentifier {
                     uri: filename.to_url()?,
                 },
-                position: Position { line, character },
+                position,
             },
         )?;
 
@@ -1555,14 +2332,14 @@ impl LanguageClient {
     pub fn textDocument_formatting(&self, params: &Value) -> Fallible<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::Formatting::METHOD);
-        let (languageId, filename, handle): (String, String, bool) = self.gather_args(
-            &[VimVar::LanguageId, VimVar::Filename, VimVar::Handle],
-            params,
-        )?;
-
-        let (tab_size, insert_spaces): (u64, u64) =
-            self.vim()?.eval(["shiftwidth()", "&expandtab"].as_ref())?;
-        let insert_spaces = insert_spaces == 1;
+        let (languageId, filename, handle, tab_size, insert_spaces): (
+            String,
+            String,
+            bool,
+            u64,
+            u64,
+        ) = self.gather_args(FORMATTING_ARGS, params)?;
+        let insert_spaces = insert_spaces == 1;
         let result = self.get_client(&Some(languageId))?.call(
             lsp::request::Formatting::METHOD,
             DocumentFormattingParams {
@@ -1595,21 +2372,16 @@ impl LanguageClient {
     pub fn textDocument_rangeFormatting(&self, params: &Value) -> Fallible<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::RangeFormatting::METHOD);
-        let (languageId, filename, handle, start_line, end_line): (String, String, bool, u64, u64) =
-            self.gather_args(
-                &[
-                    VimVar::LanguageId.to_key().as_str(),
-                    VimVar::Filename.to_key().as_str(),
-                    VimVar::Handle.to_key().as_str(),
-                    "LSP#range_start_line()",
-                    "LSP#range_end_line()",
-                ],
-                params,
-            )?;
-
-        let (tab_size, insert_spaces): (u64, u64) =
-            self.vim()?.eval(["shiftwidth()", "&expandtab"].as_ref())?;
+        let (languageId, filename, handle, tab_size, insert_spaces): (
+            String,
+            String,
+            bool,
+            u64,
+            u64,
+        ) = self.gather_args(FORMATTING_ARGS, params)?;
         let insert_spaces = insert_spaces == 1;
+        let (start_line, end_line): (u64, u64) =
+            self.gather_args(&["LSP#range_start_line()", "LSP#range_end_line()"], params)?;
         let result = self.get_client(&Some(languageId))?.call(
             lsp::request::RangeFormatting::METHOD,
             DocumentRangeFormattingParams {
@@ -1678,10 +2450,10 @@ impl LanguageClient {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::WorkspaceSymbol::METHOD);
         let (languageId, handle): (String, bool) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Handle], params)?;
+            self.gather_args(WORKSPACE_SYMBOL_ARGS, params)?;
 
         let (query,): (String,) = self.gather_args(&[("query", "")], params)?;
-        let result = self.get_client(&Some(languageId))?.call(
+        let result = self.get_client(&Some(languageId.clone()))?.call(
             lsp::request::WorkspaceSymbol::METHOD,
             WorkspaceSymbolParams { query },
         )?;
@@ -1691,6 +2463,7 @@ impl LanguageClient {
         }
 
         let symbols: Vec<SymbolInformation> = serde_json::from_value(result.clone())?;
+        let symbols = self.to_editor_symbols(&languageId, symbols)?;
         let title = "[LC]: workspace symbols";
 
         let selectionUI = self.get(|state| state.selectionUI)?;
@@ -1792,28 +2565,31 @@ impl LanguageClient {
 
     pub fn textDocument_didOpen(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", lsp::notification::DidOpenTextDocument::METHOD);
-        let (languageId, filename, text): (String, String, Vec<String>) = self.gather_args(
-            &[VimVar::LanguageId, VimVar::Filename, VimVar::Text],
-            params,
-        )?;
+        let (languageId, filename, text, format): (String, String, Vec<String>, DocumentFormat) =
+            self.gather_args(DID_OPEN_ARGS, params)?;
 
         let text_document = TextDocumentItem {
             uri: filename.to_url()?,
             language_id: languageId.clone(),
             version: 0,
-            text: text.join("\n"),
+            text: format.join(&text),
         };
 
         self.update(|state| {
-            Ok(state
+            state
                 .text_documents
-                .insert(filename.clone(), text_document.clone()))
+                .insert(filename.clone(), text_document.clone());
+            state.document_formats.insert(filename.clone(), format);
+            Ok(())
         })?;
 
-        self.get_client(&Some(languageId.clone()))?.notify(
-            lsp::notification::DidOpenTextDocument::METHOD,
-            DidOpenTextDocumentParams { text_document },
-        )?;
+        let sync_options = self.get_text_document_sync_options(&languageId)?;
+        if sync_options.open_close == Some(true) {
+            self.get_client(&Some(languageId.clone()))?.notify(
+                lsp::notification::DidOpenTextDocument::METHOD,
+                DidOpenTextDocumentParams { text_document },
+            )?;
+        }
 
         self.vim()?
             .command("setlocal omnifunc=LanguageClient#complete")?;
@@ -1822,9 +2598,28 @@ impl LanguageClient {
             "setbufvar",
             json!([filename, "LanguageClient_projectRoot", root]),
         )?;
+        let has_pre_write_actions = self.get(|state| {
+            state
+                .preWriteActions
+                .get(&languageId)
+                .iter()
+                .any(|actions| !actions.is_empty())
+        })?;
+        let handle_write_pre = sync_options.will_save == Some(true)
+            || sync_options.will_save_wait_until == Some(true)
+            || has_pre_write_actions;
+        self.vim()?.rpcclient.notify(
+            "setbufvar",
+            json!([
+                filename,
+                "LanguageClient_handleBufWritePre",
+                u8::from(handle_write_pre)
+            ]),
+        )?;
         self.vim()?
             .rpcclient
             .notify("s:ExecuteAutocmd", "LanguageClientTextDocumentDidOpenPost")?;
+        self.schedule_diagnostics_pull(&languageId, &filename)?;
 
         info!("End {}", lsp::notification::DidOpenTextDocument::METHOD);
         Ok(())
@@ -1832,17 +2627,25 @@ impl LanguageClient {
 
     pub fn textDocument_didChange(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", lsp::notification::DidChangeTextDocument::METHOD);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
         if !self.get(|state| state.text_documents.contains_key(&filename))? {
             info!("Not opened yet. Switching to didOpen.");
             return self.textDocument_didOpen(params);
         }
 
-        let (text,): (Vec<String>,) =
-            self.gather_args(&[format!("LSP#text('{}')", filename)], params)?;
+        // Changes are computed against the last synced text, so they must be sent one at a
+        // time, in order.
+        let did_change_lock = self.get(|state| state.did_change_lock.clone())?;
+        let _guard = did_change_lock
+            .lock()
+            .map_err(|err| format_err!("Failed to lock didChange: {}", err))?;
+
+        let (text, format): (Vec<String>, DocumentFormat) = self.gather_args(
+            &[text_exp(&filename), document_format_exp(&filename)],
+            params,
+        )?;
 
-        let text = text.join("\n");
+        let text = format.join(&text);
         let text_state = self.get(|state| {
             state
                 .text_documents
@@ -1855,6 +2658,24 @@ impl LanguageClient {
             return Ok(());
         }
 
+        let sync_kind = self
+            .get_text_document_sync_options(&languageId)?
+            .change
+            .unwrap_or(TextDocumentSyncKind::None);
+        let content_changes = match sync_kind {
+            TextDocumentSyncKind::None => vec![],
+            TextDocumentSyncKind::Incremental => get_text_document_content_changes(
+                &text_state,
+                &text,
+                self.get_position_encoding(&languageId)?,
+            ),
+            TextDocumentSyncKind::Full => vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.clone(),
+            }],
+        };
+
         let version = self.update(|state| {
             let document = state.text_documents.get_mut(&filename).ok_or_else(|| {
                 format_err!("Failed to get TextDocumentItem! filename: {}", filename)
@@ -1862,7 +2683,8 @@ impl LanguageClient {
 
             let version = document.version + 1;
             document.version = version;
-            document.text = text.clone();
+            document.text = text;
+            state.document_formats.insert(filename.clone(), format);
 
             if state.change_throttle.is_some() {
                 let metadata = state
@@ -1874,20 +2696,22 @@ impl LanguageClient {
             Ok(version)
         })?;
 
-        self.get_client(&Some(languageId))?.notify(
+        if sync_kind == TextDocumentSyncKind::None {
+            info!("Server does not sync changes. Skipping didChange.");
+            return Ok(());
+        }
+
+        self.get_client(&Some(languageId.clone()))?.notify(
             lsp::notification::DidChangeTextDocument::METHOD,
             DidChangeTextDocumentParams {
                 text_document: VersionedTextDocumentIdentifier {
                     uri: filename.to_url()?,
                     version: Some(version),
                 },
-                content_changes: vec![TextDocumentContentChangeEvent {
-                    range: None,
-                    range_length: None,
-                    text,
-                }],
+                content_changes,
             },
         )?;
+        self.schedule_diagnostics_pull(&languageId, &filename)?;
 
         info!("End {}", lsp::notification::DidChangeTextDocument::METHOD);
         Ok(())
@@ -1895,29 +2719,94 @@ impl LanguageClient {
 
     pub fn textDocument_didSave(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", lsp::notification::DidSaveTextDocument::METHOD);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
         if !self.get(|state| state.serverCommands.contains_key(&languageId))? {
             return Ok(());
         }
 
+        let save_options = match self.get_text_document_sync_options(&languageId)?.save {
+            Some(save_options) => save_options,
+            None => return Ok(()),
+        };
+
         let uri = filename.to_url()?;
+        let mut params = serde_json::to_value(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+        })?;
+        if save_options.include_text == Some(true) {
+            let (text, format): (Vec<String>, DocumentFormat) = self.gather_args(
+                &[text_exp(&filename), document_format_exp(&filename)],
+                &Value::Null,
+            )?;
+            params = params.combine(&json!({ "text": format.join(&text) }));
+        }
+
+        self.get_client(&Some(languageId))?
+            .notify(lsp::notification::DidSaveTextDocument::METHOD, params)?;
+
+        info!("End {}", lsp::notification::DidSaveTextDocument::METHOD);
+        Ok(())
+    }
+
+    pub fn textDocument_willSave(&self, params: &Value) -> Fallible<()> {
+        info!("Begin {}", lsp::notification::WillSaveTextDocument::METHOD);
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
 
         self.get_client(&Some(languageId))?.notify(
-            lsp::notification::DidSaveTextDocument::METHOD,
-            DidSaveTextDocumentParams {
-                text_document: TextDocumentIdentifier { uri: uri.clone() },
+            lsp::notification::WillSaveTextDocument::METHOD,
+            WillSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier {
+                    uri: filename.to_url()?,
+                },
+                reason: TextDocumentSaveReason::Manual,
             },
         )?;
-
-        info!("End {}", lsp::notification::DidSaveTextDocument::METHOD);
+        info!("End {}", lsp::notification::WillSaveTextDocument::METHOD);
         Ok(())
     }
 
+    pub fn textDocument_willSaveWaitUntil(&self, params: &Value) -> Fallible<Value> {
+        let timeout = self.get(|state| state.pre_write_timeout)?;
+        self.will_save_wait_until(params, timeout)
+    }
+
+    /// Request edits to apply before saving, waiting at most `timeout`, as the buffer is being
+    /// written. Without a timely response, the buffer is saved as it is.
+    fn will_save_wait_until(&self, params: &Value, timeout: Duration) -> Fallible<Value> {
+        self.textDocument_didChange(params)?;
+        info!("Begin {}", lsp::notification::WillSaveWaitUntil::METHOD);
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
+
+        let result: Value = match self.get_client(&Some(languageId))?.call_with_timeout(
+            lsp::notification::WillSaveWaitUntil::METHOD,
+            WillSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier {
+                    uri: filename.to_url()?,
+                },
+                reason: TextDocumentSaveReason::Manual,
+            },
+            timeout,
+        ) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Saving without edits of willSaveWaitUntil: {}", err);
+                return Ok(Value::Null);
+            }
+        };
+
+        let text_edits: Option<Vec<TextEdit>> = serde_json::from_value(result.clone())?;
+        let text_edits = text_edits.unwrap_or_default();
+        self.apply_TextEdits(&filename, &text_edits)?;
+        info!("End {}", lsp::notification::WillSaveWaitUntil::METHOD);
+        Ok(result)
+    }
+
     pub fn textDocument_didClose(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", lsp::notification::DidCloseTextDocument::METHOD);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
+        if self.get_text_document_sync_options(&languageId)?.open_close != Some(true) {
+            return Ok(());
+        }
 
         self.get_client(&Some(languageId))?.notify(
             lsp::notification::DidCloseTextDocument::METHOD,
@@ -1927,12 +2816,22 @@ impl LanguageClient {
                 },
             },
         )?;
+        // Diagnostics of closed documents come with workspace pulls.
+        self.update(|state| {
+            state.diagnostics_pulls.remove(filename.to_url()?.as_str());
+            Ok(())
+        })?;
         info!("End {}", lsp::notification::DidCloseTextDocument::METHOD);
         Ok(())
     }
 
-    pub fn textDocument_publishDiagnostics(&self, params: &Value) -> Fallible<()> {
+    pub fn textDocument_publishDiagnostics(
+        &self,
+        languageId: &str,
+        params: &Value,
+    ) -> Fallible<()> {
         info!("Begin {}", lsp::notification::PublishDiagnostics::METHOD);
+        let code_descriptions = get_code_descriptions(params);
         let params: PublishDiagnosticsParams = params.clone().to_lsp()?;
         if !self.get(|state| state.diagnosticsEnable)? {
             return Ok(());
@@ -1946,34 +2845,35 @@ impl LanguageClient {
         // Unify name to avoid mismatch due to case insensitivity.
         let filename = filename.canonicalize();
 
-        let mut diagnostics = params.diagnostics;
-        diagnostics.sort_by_key(
-            // First sort by line.
-            // Then severity descendingly. Error should come last since when processing item comes
-            // later will override its precedance.
-            // Then by character descendingly.
-            |diagnostic| {
-                (
-                    diagnostic.range.start.line,
-                    -(diagnostic.severity.unwrap_or(DiagnosticSeverity::Hint) as i8),
-                    -(diagnostic.range.start.line as i64),
-                )
-            },
-        );
-
+        // Replaced with each publish, so links of fixed diagnostics don't pile up.
         self.update(|state| {
             state
-                .diagnostics
-                .insert(filename.clone(), diagnostics.clone());
+                .code_descriptions
+                .entry(languageId.to_owned())
+                .or_default()
+                .insert(filename.clone(), code_descriptions.into_iter().collect());
+            Ok(())
+        })?;
+        let filter = self.get_diagnostics_filter(languageId)?;
+        let diagnostics = filter.apply(params.diagnostics);
+        self.update(|state| {
+            state
+                .server_diagnostics
+                .entry(languageId.to_owned())
+                .or_default()
+                .insert(filename.clone(), diagnostics);
             Ok(())
         })?;
+        self.merge_diagnostics(&filename)?;
         self.update_quickfixlist()?;
+        self.update_status()?;
 
+        // Also shown in other windows, or queued for hidden buffers.
+        self.process_diagnostics(&filename)?;
         let current_filename: String = self.vim()?.eval(VimVar::Filename)?;
         if filename != current_filename.canonicalize() {
             return Ok(());
         }
-        self.process_diagnostics(&current_filename, &diagnostics)?;
         self.languageClient_handleCursorMoved(&Value::Null)?;
         self.vim()?
             .rpcclient
@@ -1983,6 +2883,253 @@ impl LanguageClient {
         Ok(())
     }
 
+    /// Get options of pull diagnostics, if the server supports them.
+    fn get_diagnostic_provider(&self, languageId: &str) -> Fallible<Option<Value>> {
+        self.get(|state| {
+            if !state.diagnosticsEnable {
+                return None;
+            }
+            state
+                .capabilities
+                .get(languageId)
+                .map(|result| result["capabilities"]["diagnosticProvider"].clone())
+                .filter(|provider| !provider.is_null())
+        })
+    }
+
+    /// Pull diagnostics of an open document in the background. With a pull in flight, the
+    /// document is pulled again once it is done.
+    fn schedule_diagnostics_pull(&self, languageId: &str, filename: &str) -> Fallible<()> {
+        if self.get_diagnostic_provider(languageId)?.is_none() {
+            return Ok(());
+        }
+
+        let uri = filename.to_url()?;
+        let start = self.update(|state| {
+            Ok(state
+                .diagnostics_pulls
+                .entry(uri.to_string())
+                .or_default()
+                .request())
+        })?;
+        if !start {
+            return Ok(());
+        }
+
+        let language_client = LanguageClient(self.0.clone());
+        let languageId = languageId.to_owned();
+        thread::spawn(move || loop {
+            let result = language_client
+                .get(|state| {
+                    state
+                        .diagnostics_pulls
+                        .get(uri.as_str())
+                        .map(|pull| pull.version)
+                })
+                .and_then(|version| match version {
+                    Some(version) => {
+                        language_client.textDocument_diagnostic(&languageId, &uri, version)
+                    }
+                    // Closed in the meantime.
+                    None => Ok(()),
+                });
+            if let Err(err) = result {
+                error!("Failed to pull diagnostics: {:?}", err);
+            }
+            let again = language_client.update(|state| {
+                Ok(state
+                    .diagnostics_pulls
+                    .get_mut(uri.as_str())
+                    .map_or(false, DocumentPull::finish))
+            });
+            match again {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    error!("Failed to update state: {:?}", err);
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Pull diagnostics of the whole workspace in the background, unless a pull is in flight.
+    fn schedule_workspace_diagnostics_pull(&self, languageId: &str) -> Fallible<()> {
+        match self.get_diagnostic_provider(languageId)? {
+            Some(ref provider) if provider["workspaceDiagnostics"] == true => {}
+            _ => return Ok(()),
+        }
+        let in_flight = self.update(|state| {
+            Ok(!state
+                .workspace_diagnostics_pulls
+                .insert(languageId.to_owned()))
+        })?;
+        if in_flight {
+            return Ok(());
+        }
+
+        let language_client = LanguageClient(self.0.clone());
+        let languageId = languageId.to_owned();
+        thread::spawn(move || {
+            let result = language_client.workspace_diagnostic(&languageId);
+            if let Err(err) = language_client.update(|state| {
+                state.workspace_diagnostics_pulls.remove(&languageId);
+                Ok(())
+            }) {
+                error!("Failed to update state: {:?}", err);
+            }
+            if let Err(err) = result {
+                error!("Failed to pull workspace diagnostics: {:?}", err);
+            }
+        });
+        Ok(())
+    }
+
+    /// Store a pulled diagnostic report, which is either "full" or "unchanged", the same way as
+    /// published diagnostics.
+    fn handle_diagnostic_report(
+        &self,
+        languageId: &str,
+        uri: &Url,
+        report: &Value,
+    ) -> Fallible<()> {
+        let (result_id, items) = parse_diagnostic_report(report)?;
+        if let Some(result_id) = result_id {
+            self.update(|state| {
+                state
+                    .diagnostics_result_ids
+                    .entry(languageId.to_owned())
+                    .or_default()
+                    .insert(uri.to_string(), result_id);
+                Ok(())
+            })?;
+        }
+
+        if let Some(items) = items {
+            self.textDocument_publishDiagnostics(
+                languageId,
+                &json!({
+                    "uri": uri.as_str(),
+                    "diagnostics": items,
+                }),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn textDocument_diagnostic(&self, languageId: &str, uri: &Url, pull: u64) -> Fallible<()> {
+        info!("Begin {}", REQUEST__DocumentDiagnostic);
+        let provider = self
+            .get_diagnostic_provider(languageId)?
+            .unwrap_or_default();
+        let previous_result_id = self.get(|state| {
+            state
+                .diagnostics_result_ids
+                .get(languageId)
+                .and_then(|result_ids| result_ids.get(uri.as_str()))
+                .cloned()
+        })?;
+
+        let mut params = json!({
+            "textDocument": {
+                "uri": uri.as_str(),
+            },
+        });
+        if provider["identifier"].is_string() {
+            params["identifier"] = provider["identifier"].clone();
+        }
+        if let Some(previous_result_id) = previous_result_id {
+            params["previousResultId"] = json!(previous_result_id);
+        }
+        let result: Value = self
+            .get_client(&Some(languageId.to_owned()))?
+            .call(REQUEST__DocumentDiagnostic, params)?;
+
+        // Document changed in the meantime, the later pull has the up-to-date result.
+        let version = self.get(|state| {
+            state
+                .diagnostics_pulls
+                .get(uri.as_str())
+                .map(|pull| pull.version)
+        })?;
+        if version != Some(pull) {
+            info!("Dropping outdated diagnostics of {}", uri);
+            return Ok(());
+        }
+
+        self.handle_diagnostic_report(languageId, uri, &result)?;
+        if let Some(related_documents) = result["relatedDocuments"].as_object() {
+            for (uri, report) in related_documents {
+                self.handle_diagnostic_report(languageId, &Url::parse(uri)?, report)?;
+            }
+        }
+
+        info!("End {}", REQUEST__DocumentDiagnostic);
+        Ok(())
+    }
+
+    pub fn workspace_diagnostic(&self, languageId: &str) -> Fallible<()> {
+        info!("Begin {}", REQUEST__WorkspaceDiagnostic);
+        let provider = self
+            .get_diagnostic_provider(languageId)?
+            .unwrap_or_default();
+        let previous_result_ids: Vec<_> = self.get(|state| {
+            state
+                .diagnostics_result_ids
+                .get(languageId)
+                .iter()
+                .flat_map(|result_ids| result_ids.iter())
+                .map(|(uri, result_id)| json!({ "uri": uri, "value": result_id }))
+                .collect()
+        })?;
+
+        let mut params = json!({
+            "previousResultIds": previous_result_ids,
+        });
+        if provider["identifier"].is_string() {
+            params["identifier"] = provider["identifier"].clone();
+        }
+        let result: Value = self
+            .get_client(&Some(languageId.to_owned()))?
+            .call(REQUEST__WorkspaceDiagnostic, params)?;
+
+        for report in result["items"].as_array().into_iter().flatten() {
+            let uri = Url::parse(report["uri"].as_str().unwrap_or_default())?;
+            // Open documents are pulled on their own, with results matching their text.
+            if self.get(|state| state.diagnostics_pulls.contains_key(uri.as_str()))? {
+                continue;
+            }
+            self.handle_diagnostic_report(languageId, &uri, report)?;
+        }
+
+        info!("End {}", REQUEST__WorkspaceDiagnostic);
+        Ok(())
+    }
+
+    pub fn workspace_diagnostic_refresh(
+        &self,
+        languageId: &str,
+        _params: &Value,
+    ) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__WorkspaceDiagnosticRefresh);
+        let filenames: Vec<_> = self.get(|state| {
+            state
+                .text_documents
+                .iter()
+                .filter(|(_, document)| document.language_id == languageId)
+                .map(|(filename, _)| filename.clone())
+                .collect()
+        })?;
+        for filename in filenames {
+            self.schedule_diagnostics_pull(languageId, &filename)?;
+        }
+        self.schedule_workspace_diagnostics_pull(languageId)?;
+
+        info!("End {}", REQUEST__WorkspaceDiagnosticRefresh);
+        Ok(Value::Null)
+    }
+
     pub fn window_logMessage(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", lsp::notification::LogMessage::METHOD);
         let params: LogMessageParams = params.clone().to_lsp()?;
@@ -2249,8 +3396,7 @@ impl LanguageClient {
 
     pub fn languageClient_handleFileType(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__HandleFileType);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
         if filename.is_empty() {
             return Ok(());
         }
@@ -2260,10 +3406,8 @@ impl LanguageClient {
         if self.get(|state| state.clients.contains_key(&Some(languageId.clone())))? {
             self.textDocument_didOpen(params)?;
 
-            if let Some(diagnostics) =
-                self.get(|state| state.diagnostics.get(&filename).cloned())?
-            {
-                self.process_diagnostics(&filename, &diagnostics)?;
+            if self.get(|state| state.diagnostics.contains_key(&filename))? {
+                self.process_diagnostics(&filename)?;
                 self.languageClient_handleCursorMoved(params)?;
             }
         } else {
@@ -2285,52 +3429,306 @@ impl LanguageClient {
 
     pub fn languageClient_handleTextChanged(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__HandleTextChanged);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
         if !self.get(|state| state.serverCommands.contains_key(&languageId))? {
             return Ok(());
         }
 
-        let skip_notification = self.get(|state| {
-            if let Some(metadata) = state.text_documents_metadata.get(&filename) {
-                if let Some(throttle) = state.change_throttle {
-                    if metadata.last_change.elapsed() < throttle {
-                        return true;
-                    }
+        // Changes inside the throttle window are sent by a trailing flush, once no more changes
+        // happened for the throttle duration.
+        let (throttled, schedule_flush) = self.update(|state| {
+            let throttle = match state.change_throttle {
+                Some(throttle) => throttle,
+                None => return Ok((false, false)),
+            };
+            let metadata = match state.text_documents_metadata.get_mut(&filename) {
+                Some(metadata) => metadata,
+                None => return Ok((false, false)),
+            };
+            metadata.last_text_changed = Instant::now();
+            if !metadata.flush_scheduled && metadata.last_change.elapsed() >= throttle {
+                return Ok((false, false));
+            }
+            let schedule_flush = !metadata.flush_scheduled;
+            metadata.flush_scheduled = true;
+            Ok((true, schedule_flush))
+        })?;
+
+        if !throttled {
+            self.textDocument_didChange(params)?;
+        } else if schedule_flush {
+            info!("Throttling didChange. Scheduling flush.");
+            let language_client = LanguageClient(self.0.clone());
+            let params = params.clone();
+            thread::spawn(move || {
+                if let Err(err) = language_client.flush_didChange(&filename, &params) {
+                    error!("Failed to flush didChange: {:?}", err);
                 }
+            });
+        } else {
+            info!("Throttling didChange. Flush already scheduled.");
+        }
+        info!("End {}", NOTIFICATION__HandleTextChanged);
+        Ok(())
+    }
+
+    /// Send the pending changes of a document once it hasn't changed for the throttle duration.
+    fn flush_didChange(&self, filename: &str, params: &Value) -> Fallible<()> {
+        loop {
+            let wait = self.get(|state| {
+                let throttle = state.change_throttle.unwrap_or_default();
+                state
+                    .text_documents_metadata
+                    .get(filename)
+                    .and_then(|metadata| {
+                        (metadata.last_text_changed + throttle)
+                            .checked_duration_since(Instant::now())
+                    })
+            })?;
+            match wait {
+                Some(wait) if wait > Duration::from_millis(0) => thread::sleep(wait),
+                _ => break,
+            }
+        }
+
+        let is_open = self.update(|state| {
+            if let Some(metadata) = state.text_documents_metadata.get_mut(filename) {
+                metadata.flush_scheduled = false;
             }
-            false
+            Ok(state.text_documents.contains_key(filename))
         })?;
-        if skip_notification {
-            info!("Skip handleTextChanged due to throttling");
-            return Ok(());
+        // The document might have been closed in the meantime.
+        if is_open {
+            self.textDocument_didChange(params)?;
+        }
+        Ok(())
+    }
+
+    pub fn languageClient_handleBufWritePre(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__HandleBufWritePre);
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
+        if !self.get(|state| state.text_documents.contains_key(&filename))? {
+            return Ok(Value::Null);
+        }
+
+        // The server's edits and pre-write actions share the time budget.
+        let deadline = Instant::now() + self.get(|state| state.pre_write_timeout)?;
+        let sync_options = self.get_text_document_sync_options(&languageId)?;
+        if sync_options.will_save == Some(true) {
+            self.textDocument_willSave(params)?;
+        }
+        if sync_options.will_save_wait_until == Some(true) {
+            self.will_save_wait_until(params, deadline.saturating_duration_since(Instant::now()))?;
+        }
+        self.run_pre_write_actions(&languageId, &filename, params, deadline)?;
+        info!("End {}", REQUEST__HandleBufWritePre);
+        Ok(Value::Null)
+    }
+
+    /// Run the configured pre-write actions of a filetype in order, i.e., document formatting
+    /// or code actions of given kinds. Actions left when the time budget runs out are skipped.
+    fn run_pre_write_actions(
+        &self,
+        languageId: &str,
+        filename: &str,
+        params: &Value,
+        deadline: Instant,
+    ) -> Fallible<()> {
+        let actions = self.get(|state| {
+            state
+                .preWriteActions
+                .get(languageId)
+                .cloned()
+                .unwrap_or_default()
+        })?;
+
+        for action in actions {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!("Pre-write time budget exceeded. Skipping {}", action);
+                break;
+            }
+
+            // Previous actions might have changed the document.
+            self.textDocument_didChange(params)?;
+            let result = if action == "formatting" {
+                self.pre_write_formatting(languageId, filename, params, deadline - now)
+            } else {
+                self.pre_write_codeAction(languageId, filename, &action, deadline - now)
+            };
+            if let Err(err) = result {
+                warn!("Failed to run pre-write action {}: {}", action, err);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pre_write_formatting(
+        &self,
+        languageId: &str,
+        filename: &str,
+        params: &Value,
+        timeout: Duration,
+    ) -> Fallible<()> {
+        let (tab_size, insert_spaces): (u64, u64) = self.gather_args(INDENT_ARGS, params)?;
+        let insert_spaces = insert_spaces == 1;
+        let text_edits: Option<Vec<TextEdit>> = self
+            .get_client(&Some(languageId.to_owned()))?
+            .call_with_timeout(
+                lsp::request::Formatting::METHOD,
+                DocumentFormattingParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                    },
+                    options: FormattingOptions {
+                        tab_size,
+                        insert_spaces,
+                        properties: HashMap::new(),
+                    },
+                },
+                timeout,
+            )?;
+
+        let edit = lsp::WorkspaceEdit {
+            changes: Some(hashmap! {filename.to_url()? => text_edits.unwrap_or_default()}),
+            document_changes: None,
+        };
+        self.apply_WorkspaceEdit(&edit, &Value::Null)
+    }
+
+    fn pre_write_codeAction(
+        &self,
+        languageId: &str,
+        filename: &str,
+        kind: &str,
+        timeout: Duration,
+    ) -> Fallible<()> {
+        let deadline = Instant::now() + timeout;
+        // Diagnostics as published by this server, in its position encoding.
+        let diagnostics = self.get(|state| {
+            state
+                .server_diagnostics
+                .get(languageId)
+                .and_then(|diagnostics| diagnostics.get(&filename.canonicalize()))
+                .cloned()
+                .unwrap_or_default()
+        })?;
+        let (last_line, last_col): (u64, u64) = self
+            .vim()?
+            .eval(["line('$') - 1", "strlen(getline('$'))"].as_ref())?;
+        let end = self.to_lsp_position(languageId, filename, last_line, last_col)?;
+        let actions: Option<Vec<CodeActionOrCommand>> = self
+            .get_client(&Some(languageId.to_owned()))?
+            .call_with_timeout(
+                lsp::request::CodeActionRequest::METHOD,
+                CodeActionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                    },
+                    range: Range {
+                        start: Position::new(0, 0),
+                        end,
+                    },
+                    context: CodeActionContext {
+                        diagnostics,
+                        only: Some(vec![kind.to_owned()]),
+                    },
+                },
+                timeout,
+            )?;
+
+        // Edits of other actions were computed against the text before the first one, so only the
+        // first matching action is applied.
+        let action = actions.unwrap_or_default().into_iter().find_map(|action| {
+            let action = match action {
+                CodeActionOrCommand::CodeAction(action) => action,
+                CodeActionOrCommand::Command(cmd) => {
+                    info!("Skipping command without code action kind: {:?}", cmd);
+                    return None;
+                }
+            };
+            // Servers might not filter by kind. Sub-kinds, e.g., `source.fixAll.eslint` for
+            // `source.fixAll`, do match.
+            let matches_kind = action
+                .kind
+                .iter()
+                .any(|k| k == kind || k.starts_with(&format!("{}.", kind)));
+            if matches_kind {
+                Some(action)
+            } else {
+                None
+            }
+        });
+        let action = match action {
+            Some(action) => action,
+            None => return Ok(()),
+        };
+
+        if let Some(ref edit) = action.edit {
+            self.apply_WorkspaceEdit(edit, &Value::Null)?;
+        }
+        if let Some(cmd) = action.command {
+            if !self.try_handle_command_by_client(&cmd)? {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                self.get_client(&Some(languageId.to_owned()))?
+                    .call_with_timeout::<Value>(
+                        lsp::request::ExecuteCommand::METHOD,
+                        ExecuteCommandParams {
+                            command: cmd.command,
+                            arguments: cmd.arguments.unwrap_or_default(),
+                        },
+                        timeout,
+                    )?;
+            }
         }
 
-        self.textDocument_didChange(params)?;
-        info!("End {}", NOTIFICATION__HandleTextChanged);
         Ok(())
     }
 
     pub fn languageClient_handleBufWritePost(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__HandleBufWritePost);
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
+        // Make sure the server has the saved text, even if a didChange is still throttled.
+        if self.get(|state| state.text_documents.contains_key(&filename))? {
+            self.textDocument_didChange(params)?;
+        }
         self.textDocument_didSave(params)?;
+        // Saving might change diagnostics of the document, e.g., of checks run on save, and of
+        // other files.
+        if self.get(|state| state.clients.contains_key(&Some(languageId.clone())))? {
+            if self.get(|state| state.text_documents.contains_key(&filename))? {
+                self.schedule_diagnostics_pull(&languageId, &filename)?;
+            }
+            self.schedule_workspace_diagnostics_pull(&languageId)?;
+        }
         info!("End {}", NOTIFICATION__HandleBufWritePost);
         Ok(())
     }
 
     pub fn languageClient_handleBufDelete(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__HandleBufWritePost);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
         if !self.get(|state| state.serverCommands.contains_key(&languageId))? {
             return Ok(());
         }
 
         self.update(|state| {
             state.text_documents.retain(|f, _| f != &filename);
+            state.text_documents_metadata.retain(|f, _| f != &filename);
+            state.document_formats.retain(|f, _| f != &filename);
             state.diagnostics.retain(|f, _| f != &filename);
+            state.diagnostics_displays.retain(|f, _| f != &filename);
+            for diagnostics in state.server_diagnostics.values_mut() {
+                diagnostics.retain(|f, _| f != &filename);
+            }
+            for code_descriptions in state.code_descriptions.values_mut() {
+                code_descriptions.remove(&filename);
+            }
             state.line_diagnostics.retain(|fl, _| fl.0 != filename);
             state.signs.retain(|f, _| f != &filename);
+            state.diagnostics_pending.remove(&filename);
             Ok(())
         })?;
         self.textDocument_didClose(params)?;
@@ -2338,6 +3736,30 @@ impl LanguageClient {
         Ok(())
     }
 
+    pub fn languageClient_handleBufEnter(&self, params: &Value) -> Fallible<()> {
+        info!("Begin {}", NOTIFICATION__HandleBufEnter);
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
+        if !self.get(|state| state.serverCommands.contains_key(&languageId))? {
+            return Ok(());
+        }
+
+        let filename = filename.canonicalize();
+        if !self.get(|state| state.diagnostics_pending.contains(&filename))? {
+            return Ok(());
+        }
+        if self.get(|state| state.diagnostics.contains_key(&filename))? {
+            self.process_diagnostics(&filename)?;
+            self.languageClient_handleCursorMoved(params)?;
+        }
+        self.update(|state| {
+            state.diagnostics_pending.remove(&filename);
+            Ok(())
+        })?;
+
+        info!("End {}", NOTIFICATION__HandleBufEnter);
+        Ok(())
+    }
+
     pub fn languageClient_handleCursorMoved(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__HandleCursorMoved);
         let (languageId, filename, bufnr, line): (String, String, i64, u64) = self.gather_args(
@@ -2369,152 +3791,50 @@ impl LanguageClient {
             let diffs = viewport - state.viewport;
             state.viewport = viewport;
             Ok(diffs)
-        })?;
-
-        if line != self.get(|state| state.last_cursor_line)? {
-            self.update(|state| {
-                state.last_cursor_line = line;
-                Ok(())
-            })?;
-
-            let message = self.get(|state| {
-                state
-                    .line_diagnostics
-                    .get(&(filename.clone(), line))
-                    .cloned()
-                    .unwrap_or_default()
-            })?;
-
-            if message != self.get(|state| state.last_line_diagnostic.clone())? {
-                self.vim()?.echo_ellipsis(&message)?;
-                self.update(|state| {
-                    state.last_line_diagnostic = message;
-                    Ok(())
-                })?;
-            }
-        }
-
-        let signs: Vec<_> = self.update(|state| {
-            Ok(state
-                .signs
-                .entry(filename.clone())
-                .or_insert_with(|| vec![])
-                .iter()
-                .filter_map(|s| {
-                    if s.line < visible_line_start + 1 || s.line > visible_line_end + 1 {
-                        return None;
-                    }
-
-                    Some(s.clone())
-                })
-                .collect())
-        })?;
-        let signs_prev = self.get(|state| {
-            state
-                .signs_placed
-                .get(&filename)
-                .cloned()
-                .unwrap_or_default()
-        })?;
-        if signs != signs_prev {
-            let (signs, cmds) = get_command_update_signs(&signs_prev, &signs, &filename);
-            self.update(|state| {
-                state.signs_placed.insert(filename.clone(), signs);
-                Ok(())
-            })?;
-
-            info!("Updating signs: {:?}", cmds);
-            self.vim()?.command(&cmds)?;
-        }
-
-        let highlights: Vec<_> = self.update(|state| {
-            Ok(state
-                .highlights
-                .entry(filename.clone())
-                .or_insert_with(|| vec![])
-                .iter()
-                .filter_map(|h| {
-                    if h.line < visible_line_start || h.line > visible_line_end {
-                        return None;
-                    }
-
-                    Some(h.clone())
-                })
-                .collect())
-        })?;
-
-        if Some(highlights.clone())
-            != self.get(|state| state.highlights_placed.get(&filename).cloned())?
-            && self.get(|state| state.is_nvim)?
-        {
-            let source = if let Some(source) = self.get(|state| state.highlight_source)? {
-                source
-            } else {
-                let source = self
-                    .vim()?
-                    .rpcclient
-                    .call("nvim_buf_add_highlight", json!([0, 0, "Error", 1, 1, 1]))?;
-                self.update(|state| {
-                    state.highlight_source = Some(source);
-                    Ok(())
-                })?;
-                source
-            };
+        })?;
 
+        if line != self.get(|state| state.last_cursor_line)? {
             self.update(|state| {
-                state
-                    .highlights_placed
-                    .insert(filename.clone(), highlights.clone());
+                state.last_cursor_line = line;
                 Ok(())
             })?;
 
-            self.vim()?.rpcclient.notify(
-                "nvim_buf_clear_highlight",
-                json!([0, source, visible_line_start, visible_line_end]),
-            )?;
-
-            self.vim()?
-                .rpcclient
-                .notify("s:AddHighlights", json!([source, highlights]))?;
-        }
-
-        if self.get(|state| state.use_virtual_text)? {
-            let namespace_id = self.get_or_create_namespace()?;
+            let message = self.get(|state| {
+                state
+                    .line_diagnostics
+                    .get(&(filename.clone(), line))
+                    .cloned()
+                    .unwrap_or_default()
+            })?;
 
-            for viewport in &viewport_diffs {
-                let mut virtual_texts = vec![];
+            if message != self.get(|state| state.last_line_diagnostic.clone())? {
+                self.vim()?.echo_ellipsis(&message)?;
                 self.update(|state| {
-                    if let Some(diag_list) = state.diagnostics.get(&filename) {
-                        for diag in diag_list {
-                            if viewport.overlaps(diag.range) {
-                                virtual_texts.push(VirtualText {
-                                    line: diag.range.start.line,
-                                    text: diag.message.replace("\n", "  ").clone(),
-                                    hl_group: state
-                                        .diagnosticsDisplay
-                                        .get(
-                                            &(diag.severity.unwrap_or(DiagnosticSeverity::Hint)
-                                                as u64),
-                                        )
-                                        .ok_or_else(|| err_msg("Failed to get display"))?
-                                        .virtualTexthl
-                                        .clone(),
-                                });
-                            }
-                        }
-                    }
+                    state.last_line_diagnostic = message;
                     Ok(())
                 })?;
-                self.vim()?.set_virtual_texts(
-                    bufnr,
-                    namespace_id,
-                    viewport.start,
-                    viewport.end,
-                    &virtual_texts,
-                )?;
             }
         }
 
+        let visible_lines = Viewport::new(visible_line_start, visible_line_end + 1);
+        self.update_signs_and_highlights(&filename, bufnr, &visible_lines)?;
+
+        if self.get(|state| state.use_virtual_text)? {
+            // Virtual texts are shown for the cursor line only, so all of them are updated when
+            // cursor moved to another line.
+            let line_changed = self.update(|state| {
+                let changed = state.last_virtual_text_line != line;
+                state.last_virtual_text_line = line;
+                Ok(changed)
+            })?;
+            let viewports = if line_changed && self.get(|state| state.virtualTextCurrentLineOnly)? {
+                vec![viewport]
+            } else {
+                viewport_diffs
+            };
+            self.update_virtual_texts(&filename, bufnr, Some(line), &viewports)?;
+        }
+
         info!("End {}", NOTIFICATION__HandleCursorMoved);
         Ok(())
     }
@@ -2603,7 +3923,7 @@ impl LanguageClient {
             .to_int()?
             - 1;
 
-        self.vim()?.edit(&None, &filename)?;
+        self.edit(&None, &filename)?;
         self.vim()?.cursor(line + 1, character + 1)?;
 
         info!("End {}", NOTIFICATION__FZFSinkLocation);
@@ -2613,41 +3933,35 @@ impl LanguageClient {
     pub fn languageClient_FZFSinkCommand(&self, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__FZFSinkCommand);
         let (selection,): (String,) = self.gather_args(&["selection"], params)?;
-        let tokens: Vec<&str> = selection.splitn(2, ": ").collect();
-        let command = tokens
-            .get(0)
-            .cloned()
-            .ok_or_else(|| format_err!("Failed to get command! tokens: {:?}", tokens))?;
-        let title = tokens
-            .get(1)
-            .cloned()
-            .ok_or_else(|| format_err!("Failed to get title! tokens: {:?}", tokens))?;
-        let entry = self.get(|state| {
-            let commands = &state.stashed_codeAction_commands;
-
-            commands
+        let entry = self.update(|state| {
+            let actions = &mut state.stashed_codeAction_actions;
+            let idx = actions
                 .iter()
-                .find(|e| e.command == command && e.title == title)
-                .cloned()
+                .position(|e| e.to_selection_entry() == selection)
                 .ok_or_else(|| {
-                    format_err!("No stashed command found! stashed commands: {:?}", commands)
-                })
-        })??;
+                    format_err!(
+                        "No stashed code action found! stashed actions: {:?}",
+                        actions
+                    )
+                })?;
+            let entry = actions.remove(idx);
+            actions.clear();
+            Ok(entry)
+        })?;
 
-        if self.try_handle_command_by_client(&entry)? {
-            return Ok(());
+        match entry {
+            CodeActionOrCommand::Command(cmd) => self.execute_command(&cmd)?,
+            CodeActionOrCommand::CodeAction(action) => {
+                // Per spec, the edit is applied before the command is executed.
+                if let Some(ref edit) = action.edit {
+                    self.apply_WorkspaceEdit(edit, &Value::Null)?;
+                }
+                if let Some(ref cmd) = action.command {
+                    self.execute_command(cmd)?;
+                }
+            }
         }
 
-        self.workspace_executeCommand(&json!({
-            "command": entry.command,
-            "arguments": entry.arguments,
-        }))?;
-
-        self.update(|state| {
-            state.stashed_codeAction_commands = vec![];
-            Ok(())
-        })?;
-
         info!("End {}", NOTIFICATION__FZFSinkCommand);
         Ok(())
     }
@@ -2745,12 +4059,484 @@ impl LanguageClient {
     pub fn languageClient_explainErrorAtPoint(&self, params: &Value) -> Fallible<Value> {
         info!("Begin {}", REQUEST__ExplainErrorAtPoint);
         let (filename, line, character): (String, u64, u64) =
-            self.gather_args(&[VimVar::Filename, VimVar::Line, VimVar::Character], params)?;
-        let position = lsp::Position::new(line, character);
-        let diag = self.get(|state| {
+            self.gather_args(CURSOR_ARGS, params)?;
+        let diag = self.get_diagnostic_at_point(&filename, line, character)?;
+
+        let mut explanation = diag.message.clone();
+        if let Some(ref code) = diag.code {
+            let code = code.to_string();
+            let href = self.get(|state| {
+                let mut languageIds: Vec<_> = state.code_descriptions.keys().collect();
+                languageIds.sort();
+                languageIds.into_iter().find_map(|languageId| {
+                    state.code_descriptions[languageId]
+                        .get(&filename)?
+                        .get(&code)
+                        .cloned()
+                })
+            })?;
+            if let Some(href) = href {
+                explanation += &format!("\n\n{}", href);
+            }
+        }
+        let related = diag.related_information.unwrap_or_default();
+        if !related.is_empty() {
+            explanation += "\n\nRelated information:";
+            for info in related {
+                let loc = info.location;
+                explanation += &format!(
+                    "\n{}:{}:{}: {}",
+                    loc.uri.filepath()?.to_string_lossy(),
+                    loc.range.start.line + 1,
+                    loc.range.start.character + 1,
+                    info.message
+                );
+            }
+        }
+        self.preview(explanation.as_str())?;
+
+        info!("End {}", REQUEST__ExplainErrorAtPoint);
+        Ok(Value::Null)
+    }
+
+    pub fn languageClient_gotoRelatedInformation(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__GotoRelatedInformation);
+        let (filename, line, character, goto_cmd): (String, u64, u64, Option<String>) =
+            self.gather_args(RELATED_INFORMATION_ARGS, params)?;
+        let diag = self.get_diagnostic_at_point(&filename, line, character)?;
+        let locations: Vec<_> = diag
+            .related_information
+            .unwrap_or_default()
+            .into_iter()
+            .map(|info| info.location)
+            .collect();
+
+        match locations.len() {
+            0 => self.vim()?.echowarn("No related information!")?,
+            1 => {
+                let loc = &locations[0];
+                self.edit(&goto_cmd, loc.uri.filepath()?)?;
+                self.vim()?
+                    .cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
+            }
+            _ => self.display_locations(&locations, "[LC]: related information")?,
+        }
+
+        info!("End {}", REQUEST__GotoRelatedInformation);
+        Ok(Value::Null)
+    }
+
+    pub fn languageClient_exportDiagnostics(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__ExportDiagnostics);
+        let (languageId, filename): (String, String) = self.gather_args(BUFFER_ARGS, params)?;
+        let (format, path, workspace): (String, String, u8) =
+            self.gather_args(&["format", "path", "workspace"], params)?;
+        let format = ExportFormat::from_str(&format)?;
+        let workspace = workspace == 1;
+
+        // Paths are relative to the root of current project, or else of the project containing
+        // file. Only files of current project are exported for the workspace.
+        let diagnostics = self.get(|state| state.diagnostics.clone())?;
+        let (root, filenames) = if workspace {
+            let root = self
+                .get(|state| state.roots.get(&languageId).cloned())?
+                .ok_or_else(|| format_err!("No project root found! languageId: {}", languageId))?;
+            let mut filenames = vec![];
+            for (f, file_diagnostics) in &diagnostics {
+                if !file_diagnostics.is_empty()
+                    && self.get_file_root(Some(&languageId), f)?.as_ref() == Some(&root)
+                {
+                    filenames.push(f.clone());
+                }
+            }
+            filenames.sort();
+            (Some(root), filenames)
+        } else {
+            let root = self.get_file_root(Some(&languageId), &filename)?;
+            (root, vec![filename.clone()])
+        };
+
+        let mut files = vec![];
+        for f in &filenames {
+            let relpath = match root {
+                Some(ref root) => get_relative_path(f, root),
+                None => f.clone(),
+            };
+            let file_diagnostics = diagnostics.get(f).cloned().unwrap_or_default();
+            files.push((relpath, file_diagnostics, self.get_document_lines(f)?));
+        }
+
+        let count: usize = files
+            .iter()
+            .map(|(_, diagnostics, _)| diagnostics.len())
+            .sum();
+        let output = export_diagnostics(format, root.as_deref(), &files)?;
+        std::fs::write(&path, output)
+            .with_context(|err| format!("Failed to write file ({}): {}", path, err))?;
+        self.vim()?
+            .echomsg(format!("Exported {} diagnostics to {}", count, path))?;
+
+        info!("End {}", REQUEST__ExportDiagnostics);
+        Ok(Value::Null)
+    }
+
+    pub fn languageClient_writeDiagnosticsBaseline(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__WriteDiagnosticsBaseline);
+        let (languageId,): (String,) = self.gather_args(LANGUAGE_ARGS, params)?;
+        let root = self
+            .get(|state| state.roots.get(&languageId).cloned())?
+            .ok_or_else(|| format_err!("No project root found! languageId: {}", languageId))?;
+
+        let files: Vec<(String, String)> = self.get(|state| {
+            state
+                .server_diagnostics
+                .iter()
+                .flat_map(|(languageId, diagnostics)| {
+                    diagnostics
+                        .keys()
+                        .map(move |f| (languageId.clone(), f.clone()))
+                })
+                .collect()
+        })?;
+        let mut filenames = vec![];
+        for (fileLanguageId, f) in files {
+            if self.get_file_root(Some(&fileLanguageId), &f)?.as_ref() == Some(&root)
+                && !filenames.contains(&f)
+            {
+                filenames.push(f);
+            }
+        }
+        filenames.sort();
+
+        // Diagnostics already in the baseline are kept in the new one.
+        self.update(|state| {
+            state
+                .diagnostics_baselines
+                .insert(root.clone(), DiagnosticsBaseline::default());
+            Ok(())
+        })?;
+        let mut files = vec![];
+        for f in &filenames {
+            let diagnostics = self.merge_diagnostics(f)?;
+            files.push((get_relative_path(f, &root), diagnostics));
+        }
+        let baseline = DiagnosticsBaseline::new(&files);
+
+        let path = Path::new(&root).join(&self.get(|state| state.diagnosticsBaselinePath.clone())?);
+        baseline.write(&path)?;
+        let count = baseline.diagnostics.len();
+        self.update(|state| {
+            state.diagnostics_baselines.insert(root.clone(), baseline);
+            Ok(())
+        })?;
+
+        for f in &filenames {
+            self.merge_diagnostics(f)?;
+            self.process_diagnostics(f)?;
+        }
+        self.update(|state| {
+            state.last_cursor_line = 0;
+            Ok(())
+        })?;
+        self.languageClient_handleCursorMoved(&Value::Null)?;
+        self.update_quickfixlist()?;
+        self.update_status()?;
+        self.vim()?.echomsg(format!(
+            "Wrote {} diagnostics to baseline {}",
+            count,
+            path.to_string_lossy()
+        ))?;
+
+        info!("End {}", REQUEST__WriteDiagnosticsBaseline);
+        Ok(Value::Null)
+    }
+
+    pub fn languageClient_gotoDiagnostic(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__GotoDiagnostic);
+        let (filename, line, character): (String, u64, u64) =
+            self.gather_args(CURSOR_ARGS, params)?;
+        let (direction, severity, wrap): (String, Option<u64>, u8) =
+            self.gather_args(&["direction", "severity", "wrap"], params)?;
+        let forward = match direction.as_str() {
+            "next" => true,
+            "previous" => false,
+            _ => bail!("Invalid direction: {}", direction),
+        };
+        let wrap = wrap == 1;
+
+        // Diagnostics are in editor columns.
+        let position = Position::new(line, character);
+        let diagnostics = self.get(|state| state.diagnostics.clone())?;
+        let matching = |filename: &str| -> Vec<Diagnostic> {
+            diagnostics
+                .get(filename)
+                .map(|dns| {
+                    dns.iter()
+                        .filter(|dn| DiagnosticsFilter::shows(severity, dn))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut target = find_diagnostic(&matching(&filename), Some(position), forward)
+            .map(|dn| (filename.clone(), dn));
+        if target.is_none() && wrap {
+            // Continue in next (or previous) file with matching diagnostics, eventually coming
+            // back to current file.
+            let mut filenames: Vec<_> = diagnostics
+                .keys()
+                .filter(|f| *f == &filename || !matching(f).is_empty())
+                .cloned()
+                .collect();
+            filenames.sort();
+            if !forward {
+                filenames.reverse();
+            }
+            let idx = filenames.iter().position(|f| f == &filename).unwrap_or(0);
+            let mid = (idx + 1) % filenames.len().max(1);
+            filenames.rotate_left(mid);
+            target = filenames
+                .into_iter()
+                .find_map(|f| find_diagnostic(&matching(&f), None, forward).map(|dn| (f, dn)));
+        }
+
+        let (target_filename, diagnostic) = match target {
+            Some(target) => target,
+            None => {
+                self.vim()?.echowarn("No more diagnostics!")?;
+                return Ok(Value::Null);
+            }
+        };
+
+        if target_filename != filename {
+            self.edit(&None, &target_filename)?;
+        }
+        let start = diagnostic.range.start;
+        self.vim()?.cursor(start.line + 1, start.character + 1)?;
+
+        let message = get_line_diagnostic(&diagnostic);
+        self.vim()?.echo_ellipsis(&message)?;
+        // Keep the message when cursor moved handler runs.
+        self.update(|state| {
+            state.last_cursor_line = start.line;
+            state.last_line_diagnostic = message;
+            Ok(())
+        })?;
+
+        info!("End {}", REQUEST__GotoDiagnostic);
+        Ok(Value::Null)
+    }
+
+    pub fn languageClient_callHierarchy(&self, params: &Value) -> Fallible<Value> {
+        self.textDocument_didChange(params)?;
+        info!("Begin {}", REQUEST__CallHierarchy);
+        let (languageId, filename, line, character): (String, String, u64, u64) =
+            self.gather_args(POINT_ARGS, params)?;
+        let (direction, display): (String, String) =
+            self.gather_args(&["direction", "display"], params)?;
+        let direction = CallHierarchyDirection::from_str(&direction)?;
+
+        let position = self.to_lsp_position(&languageId, &filename, line, character)?;
+        let result: Value = self.get_client(&Some(languageId.clone()))?.call(
+            REQUEST__PrepareCallHierarchy,
+            TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: filename.to_url()?,
+                },
+                position,
+            },
+        )?;
+        let items: Option<Vec<CallHierarchyItem>> = serde_json::from_value(result)?;
+        let mut root = match items.unwrap_or_default().into_iter().next() {
+            Some(item) => CallHierarchyNode::new(item),
+            None => {
+                self.vim()?.echowarn("Not found!")?;
+                return Ok(Value::Null);
+            }
+        };
+        let children = self.get_calls(&languageId, direction, &root.item)?;
+
+        match display.as_str() {
+            "tree" => {
+                root.children = Some(children);
+                root.expanded = true;
+                self.update(|state| {
+                    state.call_hierarchy = Some(CallHierarchy {
+                        languageId,
+                        direction,
+                        root,
+                        paths: vec![],
+                    });
+                    Ok(())
+                })?;
+                self.show_call_hierarchy()?;
+            }
+            "list" => {
+                let mut locations = vec![];
+                for node in children {
+                    let uri = Url::parse(&node.call_uri)?;
+                    for range in node.call_ranges {
+                        locations.push(Location {
+                            uri: uri.clone(),
+                            range,
+                        });
+                    }
+                }
+                if locations.is_empty() {
+                    self.vim()?.echowarn("Not found!")?;
+                    return Ok(Value::Null);
+                }
+                let locations = self.to_editor_locations(&languageId, locations)?;
+                let title = format!("[LC]: {} calls of {}", direction.name(), root.item.name);
+                self.display_locations(&locations, &title)?;
+            }
+            _ => bail!("Invalid call hierarchy display: {}", display),
+        }
+
+        info!("End {}", REQUEST__CallHierarchy);
+        Ok(Value::Null)
+    }
+
+    /// Fetch the callers or callees of a call hierarchy item.
+    fn get_calls(
+        &self,
+        languageId: &str,
+        direction: CallHierarchyDirection,
+        item: &CallHierarchyItem,
+    ) -> Fallible<Vec<CallHierarchyNode>> {
+        let result: Value = self
+            .get_client(&Some(languageId.to_owned()))?
+            .call(direction.method(), json!({ "item": item }))?;
+        CallHierarchyNode::from_calls(direction, item, result)
+    }
+
+    /// Render the call hierarchy into its buffer.
+    fn show_call_hierarchy(&self) -> Fallible<()> {
+        let lines = self.update(|state| {
+            let hierarchy = state
+                .call_hierarchy
+                .as_mut()
+                .ok_or_else(|| err_msg("No call hierarchy!"))?;
+            let (lines, paths) = hierarchy.root.render();
+            hierarchy.paths = paths;
+            Ok(lines)
+        })?;
+        self.vim()?
+            .rpcclient
+            .notify("s:ShowCallHierarchy", json!([lines]))
+    }
+
+    /// Get the path of the call hierarchy node on a line of its buffer.
+    fn get_call_hierarchy_path(&self, line: u64) -> Fallible<Vec<usize>> {
+        self.get(|state| {
+            state
+                .call_hierarchy
+                .as_ref()
+                .and_then(|hierarchy| hierarchy.paths.get(line as usize))
+                .cloned()
+                .ok_or_else(|| err_msg("No call hierarchy item on this line!"))
+        })?
+    }
+
+    pub fn languageClient_callHierarchyToggle(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__CallHierarchyToggle);
+        let (line,): (u64,) = self.gather_args(&[VimVar::Line], params)?;
+        let path = self.get_call_hierarchy_path(line)?;
+
+        let (languageId, direction, item, fetched) = self.get(|state| -> Fallible<_> {
+            let hierarchy = state
+                .call_hierarchy
+                .as_ref()
+                .ok_or_else(|| err_msg("No call hierarchy!"))?;
+            let node = hierarchy
+                .root
+                .get(&path)
+                .ok_or_else(|| err_msg("No call hierarchy item on this line!"))?;
+            Ok((
+                hierarchy.languageId.clone(),
+                hierarchy.direction,
+                node.item.clone(),
+                node.children.is_some(),
+            ))
+        })??;
+        let children = if fetched {
+            None
+        } else {
+            Some(self.get_calls(&languageId, direction, &item)?)
+        };
+
+        self.update(|state| {
+            if let Some(node) = state
+                .call_hierarchy
+                .as_mut()
+                .and_then(|hierarchy| hierarchy.root.get_mut(&path))
+            {
+                if children.is_some() {
+                    node.children = children;
+                }
+                node.expanded = !node.expanded;
+            }
+            Ok(())
+        })?;
+        self.show_call_hierarchy()?;
+
+        info!("End {}", REQUEST__CallHierarchyToggle);
+        Ok(Value::Null)
+    }
+
+    pub fn languageClient_callHierarchyJump(&self, params: &Value) -> Fallible<Value> {
+        info!("Begin {}", REQUEST__CallHierarchyJump);
+        let (line,): (u64,) = self.gather_args(&[VimVar::Line], params)?;
+        let path = self.get_call_hierarchy_path(line)?;
+
+        let (languageId, uri, range) = self.get(|state| -> Fallible<_> {
+            let hierarchy = state
+                .call_hierarchy
+                .as_ref()
+                .ok_or_else(|| err_msg("No call hierarchy!"))?;
+            let node = hierarchy
+                .root
+                .get(&path)
+                .ok_or_else(|| err_msg("No call hierarchy item on this line!"))?;
+            let range = node
+                .call_ranges
+                .first()
+                .cloned()
+                .unwrap_or(node.item.selection_range);
+            Ok((hierarchy.languageId.clone(), node.call_uri.clone(), range))
+        })??;
+        let location = Location {
+            uri: Url::parse(&uri)?,
+            range,
+        };
+        let loc = self
+            .to_editor_locations(&languageId, vec![location])?
+            .remove(0);
+
+        // Jump from the call hierarchy buffer into previous window.
+        self.vim()?.command("wincmd p")?;
+        self.edit(&None, loc.uri.filepath()?)?;
+        self.vim()?
+            .cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
+
+        info!("End {}", REQUEST__CallHierarchyJump);
+        Ok(Value::Null)
+    }
+
+    /// Get the diagnostic under the cursor.
+    /// Get the diagnostic at an editor position, i.e., 0-based line and byte column.
+    fn get_diagnostic_at_point(
+        &self,
+        filename: &str,
+        line: u64,
+        character: u64,
+    ) -> Fallible<Diagnostic> {
+        let position = Position::new(line, character);
+        self.get(|state| {
             state
                 .diagnostics
-                .get(&filename)
+                .get(filename)
                 .ok_or_else(|| format_err!("No diagnostics found: filename: {}", filename,))?
                 .iter()
                 .find(|dn| position >= dn.range.start && position < dn.range.end)
@@ -2763,11 +4549,7 @@ impl LanguageClient {
                         character
                     )
                 })
-        })??;
-        self.preview(diag.message.as_str())?;
-
-        info!("End {}", REQUEST__ExplainErrorAtPoint);
-        Ok(Value::Null)
+        })?
     }
 
     // Extensions by language servers.
@@ -2810,7 +4592,7 @@ impl LanguageClient {
         Ok(())
     }
 
-    pub fn window_progress(&self, params: &Value) -> Fallible<()> {
+    pub fn window_progress(&self, languageId: &str, params: &Value) -> Fallible<()> {
         info!("Begin {}", NOTIFICATION__WindowProgress);
         let params: WindowProgressParams = params.clone().to_lsp()?;
 
@@ -2818,8 +4600,15 @@ impl LanguageClient {
 
         let mut buf = "LS: ".to_owned();
 
+        let id = params.id.clone().unwrap_or_default();
         if done {
             buf += "Idle";
+            self.update(|state| {
+                if let Some(progress) = state.server_progress.get_mut(languageId) {
+                    progress.remove(&id);
+                }
+                Ok(())
+            })?;
         } else {
             // For RLS this can be "Build" or "Diagnostics" or "Indexing".
             buf += params
@@ -2836,7 +4625,18 @@ impl LanguageClient {
             if let Some(percentage) = params.percentage {
                 buf += &format!(" ({:.1}% done)", percentage);
             }
+
+            let task = buf.trim_start_matches("LS: ").to_owned();
+            self.update(|state| {
+                state
+                    .server_progress
+                    .entry(languageId.to_owned())
+                    .or_default()
+                    .insert(id, task);
+                Ok(())
+            })?;
         }
+        self.update_status()?;
 
         self.vim()?.command(vec![
             format!("let {}={}", VIM__ServerStatus, if done { 0 } else { 1 }),
@@ -2962,8 +4762,12 @@ impl LanguageClient {
         )?;
         self.update(|state| {
             state.clients.insert(Some(languageId.clone()), client);
+            state
+                .server_states
+                .insert(languageId.clone(), ServerState::Starting);
             Ok(())
         })?;
+        self.update_status()?;
 
         info!("End {}", REQUEST__StartServer);
 
@@ -2973,6 +4777,13 @@ impl LanguageClient {
 
         self.initialize(&params)?;
         self.initialized(&params)?;
+        self.update(|state| {
+            state
+                .server_states
+                .insert(languageId.clone(), ServerState::Running);
+            Ok(())
+        })?;
+        self.update_status()?;
 
         let root = self.get(|state| state.roots.get(&languageId).cloned().unwrap_or_default())?;
         match self.get_workspace_settings(&root) {
@@ -2986,6 +4797,7 @@ impl LanguageClient {
 
         self.textDocument_didOpen(&params)?;
         self.textDocument_didChange(&params)?;
+        self.schedule_workspace_diagnostics_pull(&languageId)?;
 
         self.vim()?
             .rpcclient
@@ -3003,6 +4815,15 @@ impl LanguageClient {
             if let Err(err) = self.cleanup(&languageId) {
                 error!("Error in cleanup: {:?}", err);
             }
+            self.update(|state| {
+                state
+                    .server_states
+                    .insert(languageId.clone(), ServerState::Crashed);
+                Ok(())
+            })?;
+            if let Err(err) = self.update_status() {
+                error!("Error in update_status: {:?}", err);
+            }
             if let Err(err) = self.vim()?.echoerr(format!(
                 "Language server {} exited unexpectedly: {}",
                 languageId, message
@@ -3109,6 +4930,23 @@ impl LanguageClient {
                 "Log file: {}\n",
                 state.loggingFile.clone().unwrap_or_default()
             );
+
+            let mut round_trip_metrics: Vec<_> = state.round_trip_metrics.iter().collect();
+            round_trip_metrics.sort_by_key(|(method, _)| method.to_owned());
+            msg += "Editor round-trips per message (average, of which prefetching, max, \
+                    average without prefetching):\n";
+            for (method, metrics) in round_trip_metrics {
+                let messages = metrics.messages.max(1) as f64;
+                msg += &format!(
+                    "  {}: {:.1}, {:.1}, {}, {:.1} ({} messages)\n",
+                    method,
+                    metrics.round_trips as f64 / messages,
+                    metrics.prefetch_round_trips as f64 / messages,
+                    metrics.max_round_trips,
+                    metrics.unprefetched_round_trips as f64 / messages,
+                    metrics.messages,
+                );
+            }
         })?;
         self.vim()?.echo(&msg)?;
         info!("End {}", REQUEST__DebugInfo);
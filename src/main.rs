@@ -41,7 +41,14 @@ mod types;
 use crate::types::*;
 mod utils;
 use crate::utils::*;
+mod capabilities;
+use crate::capabilities::*;
 mod context;
+use crate::context::*;
+mod call_hierarchy;
+use crate::call_hierarchy::*;
+mod diagnostics_export;
+use crate::diagnostics_export::*;
 mod language_client;
 mod language_server_protocol;
 mod logger;
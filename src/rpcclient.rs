@@ -1,6 +1,21 @@
 use super::*;
 use crate::types::Call;
 use crate::vim;
+use std::cell::Cell;
+
+thread_local! {
+    /// Number of calls made to vim by current thread.
+    static EDITOR_ROUND_TRIPS: Cell<u64> = Cell::new(0);
+}
+
+/// Number of calls made to vim by current thread so far. As every message is handled in its own
+/// thread, the difference before and after handling is the number of round-trips it took.
+pub fn editor_round_trips() -> u64 {
+    EDITOR_ROUND_TRIPS.with(Cell::get)
+}
+
+/// Where to send the response to a call.
+type OutputSender = crossbeam_channel::Sender<rpc::Output>;
 
 #[derive(Clone, Serialize)]
 pub struct RpcClient {
@@ -10,7 +25,7 @@ pub struct RpcClient {
     #[serde(skip_serializing)]
     writer: Arc<Mutex<Write + Send>>,
     #[serde(skip_serializing)]
-    tx: crossbeam_channel::Sender<(Id, crossbeam_channel::Sender<rpc::Output>)>,
+    tx: crossbeam_channel::Sender<(Id, Option<OutputSender>)>,
     pub process_id: Option<u32>,
 }
 
@@ -23,10 +38,8 @@ impl RpcClient {
         process_id: Option<u32>,
         sink: crossbeam_channel::Sender<Call>,
     ) -> Fallible<Self> {
-        let (tx, rx): (
-            crossbeam_channel::Sender<(Id, crossbeam_channel::Sender<rpc::Output>)>,
-            _,
-        ) = crossbeam_channel::unbounded();
+        let (tx, rx): (crossbeam_channel::Sender<(Id, Option<OutputSender>)>, _) =
+            crossbeam_channel::unbounded();
 
         let languageId_clone = languageId.clone();
         let reader_thread_name = format!("reader-{:?}", languageId);
@@ -102,13 +115,18 @@ impl RpcClient {
                             }
                             vim::RawMessage::Output(output) => {
                                 while let Ok((id, tx)) = rx.try_recv() {
-                                    pending_outputs.insert(id, tx);
+                                    match tx {
+                                        Some(tx) => pending_outputs.insert(id, tx),
+                                        // The call has timed out.
+                                        None => pending_outputs.remove(&id),
+                                    };
                                 }
 
                                 if let Some(tx) = pending_outputs.remove(&output.id().to_int()?) {
-                                    tx.send(output).map_err(|output| {
-                                        format_err!("Failed to send output: {:?}", output)
-                                    })?;
+                                    // The receiver is gone if the call has timed out already.
+                                    if let Err(err) = tx.send(output) {
+                                        warn!("Failed to send output: {:?}", err);
+                                    }
                                 }
                             }
                         };
@@ -169,6 +187,16 @@ impl RpcClient {
         &self,
         method: impl AsRef<str>,
         params: impl Serialize,
+    ) -> Fallible<R> {
+        // TODO: duration from config.
+        self.call_with_timeout(method, params, Duration::from_secs(60))
+    }
+
+    pub fn call_with_timeout<R: DeserializeOwned>(
+        &self,
+        method: impl AsRef<str>,
+        params: impl Serialize,
+        timeout: Duration,
     ) -> Fallible<R> {
         let method = method.as_ref();
         let id = {
@@ -186,10 +214,19 @@ impl RpcClient {
             params: params.to_params()?,
         };
         let (tx, rx) = crossbeam_channel::unbounded();
-        self.tx.send((id, tx))?;
+        self.tx.send((id, Some(tx)))?;
+        if self.languageId.is_none() {
+            EDITOR_ROUND_TRIPS.with(|count| count.set(count.get() + 1));
+        }
         self.write(&msg)?;
-        // TODO: duration from config.
-        match rx.recv_timeout(Duration::from_secs(60))? {
+        let output = match rx.recv_timeout(timeout) {
+            Ok(output) => output,
+            Err(_) => {
+                self.tx.send((id, None))?;
+                bail!("Timed out waiting for response to {}", method);
+            }
+        };
+        match output {
             rpc::Output::Success(ok) => Ok(serde_json::from_value(ok.result)?),
             rpc::Output::Failure(err) => bail!("Error: {:?}", err),
         }
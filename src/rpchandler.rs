@@ -2,9 +2,19 @@ use super::*;
 use crate::language_client::LanguageClient;
 use crate::lsp::notification::Notification;
 use crate::lsp::request::Request;
+use crate::rpcclient::editor_round_trips;
 
 impl LanguageClient {
     pub fn handle_call(&self, msg: Call) -> Fallible<()> {
+        let round_trips_start = editor_round_trips();
+        let saved_round_trips_start = saved_round_trips();
+        let (msg, prefetch_round_trips) = self.prefetch_context(msg);
+        let message_from_vim = match msg {
+            Call::MethodCall(None, ref method_call) => Some(method_call.method.clone()),
+            Call::Notification(None, ref notification) => Some(notification.method.clone()),
+            _ => None,
+        };
+
         match msg {
             Call::MethodCall(lang_id, method_call) => {
                 let result = self.handle_method_call(lang_id.as_deref(), &method_call);
@@ -36,6 +46,25 @@ impl LanguageClient {
             }
         }
 
+        if let Some(method) = message_from_vim {
+            let round_trips = editor_round_trips() - round_trips_start;
+            let saved_round_trips = saved_round_trips() - saved_round_trips_start;
+            info!(
+                "{} took {} editor round-trips, {} without prefetching",
+                method,
+                round_trips,
+                round_trips - prefetch_round_trips + saved_round_trips
+            );
+            self.update(|state| {
+                state.round_trip_metrics.entry(method).or_default().record(
+                    prefetch_round_trips,
+                    round_trips,
+                    saved_round_trips,
+                );
+                Ok(())
+            })?;
+        }
+
         // TODO
         if let Err(err) = self.handle_fs_events() {
             warn!("{:?}", err);
@@ -44,6 +73,64 @@ impl LanguageClient {
         Ok(())
     }
 
+    /// Complete params of a message from vim with everything its handler will evaluate in the
+    /// editor, in one round-trip. Returns the message and the number of round-trips taken.
+    fn prefetch_context(&self, msg: Call) -> (Call, u64) {
+        let round_trips_start = editor_round_trips();
+        let msg = match msg {
+            Call::MethodCall(None, mut method_call) => {
+                if let Some(params) =
+                    self.try_prefetch_params(&method_call.method, &method_call.params)
+                {
+                    method_call.params = params;
+                }
+                Call::MethodCall(None, method_call)
+            }
+            Call::Notification(None, mut notification) => {
+                if let Some(params) =
+                    self.try_prefetch_params(&notification.method, &notification.params)
+                {
+                    notification.params = params;
+                }
+                Call::Notification(None, notification)
+            }
+            _ => msg,
+        };
+        (msg, editor_round_trips() - round_trips_start)
+    }
+
+    fn try_prefetch_params(&self, method: &str, params: &Params) -> Option<Params> {
+        match self.prefetch_params(method, params) {
+            Ok(params) => Some(params),
+            Err(err) => {
+                // Handlers evaluate what they need themselves.
+                warn!("Failed to prefetch context of {}: {:?}", method, err);
+                None
+            }
+        }
+    }
+
+    fn prefetch_params(&self, method: &str, params: &Params) -> Fallible<Params> {
+        let params = serde_json::to_value(params)?;
+        if !params.is_object() {
+            return params.to_params();
+        }
+
+        // Handlers return early for buffers without language server.
+        if let Some(languageId) = params
+            .get(VimVar::LanguageId.to_key())
+            .and_then(Value::as_str)
+        {
+            if !self.get(|state| state.serverCommands.contains_key(languageId))? {
+                return params.to_params();
+            }
+        }
+
+        Context::new(method, &params)
+            .prefetch(&self.vim()?)?
+            .to_params()
+    }
+
     pub fn handle_method_call(
         &self,
         languageId: Option<&str>,
@@ -66,6 +153,9 @@ impl LanguageClient {
             }
             lsp::request::HoverRequest::METHOD => self.textDocument_hover(&params),
             REQUEST__FindLocations => self.find_locations(&params),
+            REQUEST__CallHierarchy => self.languageClient_callHierarchy(&params),
+            REQUEST__CallHierarchyToggle => self.languageClient_callHierarchyToggle(&params),
+            REQUEST__CallHierarchyJump => self.languageClient_callHierarchyJump(&params),
             lsp::request::Rename::METHOD => self.textDocument_rename(&params),
             lsp::request::DocumentSymbolRequest::METHOD => {
                 self.textDocument_documentSymbol(&params)
@@ -76,6 +166,9 @@ impl LanguageClient {
             lsp::request::SignatureHelpRequest::METHOD => self.textDocument_signatureHelp(&params),
             lsp::request::References::METHOD => self.textDocument_references(&params),
             lsp::request::Formatting::METHOD => self.textDocument_formatting(&params),
+            lsp::notification::WillSaveWaitUntil::METHOD => {
+                self.textDocument_willSaveWaitUntil(&params)
+            }
             lsp::request::RangeFormatting::METHOD => self.textDocument_rangeFormatting(&params),
             lsp::request::ResolveCompletionItem::METHOD => self.completionItem_resolve(&params),
             lsp::request::ExecuteCommand::METHOD => self.workspace_executeCommand(&params),
@@ -83,6 +176,9 @@ impl LanguageClient {
             lsp::request::DocumentHighlightRequest::METHOD => {
                 self.textDocument_documentHighlight(&params)
             }
+            REQUEST__WorkspaceDiagnosticRefresh => {
+                self.workspace_diagnostic_refresh(languageId.unwrap_or_default(), &params)
+            }
             // Extensions.
             REQUEST__GetState => self.languageClient_getState(&params),
             REQUEST__IsAlive => self.languageClient_isAlive(&params),
@@ -94,9 +190,16 @@ impl LanguageClient {
             REQUEST__NCMRefresh => self.NCM_refresh(&params),
             REQUEST__NCM2OnComplete => self.NCM2_on_complete(&params),
             REQUEST__ExplainErrorAtPoint => self.languageClient_explainErrorAtPoint(&params),
+            REQUEST__GotoRelatedInformation => self.languageClient_gotoRelatedInformation(&params),
+            REQUEST__ExportDiagnostics => self.languageClient_exportDiagnostics(&params),
+            REQUEST__GotoDiagnostic => self.languageClient_gotoDiagnostic(&params),
+            REQUEST__WriteDiagnosticsBaseline => {
+                self.languageClient_writeDiagnosticsBaseline(&params)
+            }
             REQUEST__OmniComplete => self.languageClient_omniComplete(&params),
             REQUEST__ClassFileContents => self.java_classFileContents(&params),
             REQUEST__DebugInfo => self.debug_info(&params),
+            REQUEST__HandleBufWritePre => self.languageClient_handleBufWritePre(&params),
 
             _ => {
                 let languageId_target = if languageId.is_some() {
@@ -146,12 +249,15 @@ impl LanguageClient {
             lsp::notification::DidChangeTextDocument::METHOD => {
                 self.textDocument_didChange(&params)?
             }
+            lsp::notification::WillSaveTextDocument::METHOD => {
+                self.textDocument_willSave(&params)?
+            }
             lsp::notification::DidSaveTextDocument::METHOD => self.textDocument_didSave(&params)?,
             lsp::notification::DidCloseTextDocument::METHOD => {
                 self.textDocument_didClose(&params)?
             }
             lsp::notification::PublishDiagnostics::METHOD => {
-                self.textDocument_publishDiagnostics(&params)?
+                self.textDocument_publishDiagnostics(languageId.unwrap_or_default(), &params)?
             }
             lsp::notification::LogMessage::METHOD => self.window_logMessage(&params)?,
             lsp::notification::ShowMessage::METHOD => self.window_showMessage(&params)?,
@@ -162,6 +268,7 @@ impl LanguageClient {
             NOTIFICATION__HandleTextChanged => self.languageClient_handleTextChanged(&params)?,
             NOTIFICATION__HandleBufWritePost => self.languageClient_handleBufWritePost(&params)?,
             NOTIFICATION__HandleBufDelete => self.languageClient_handleBufDelete(&params)?,
+            NOTIFICATION__HandleBufEnter => self.languageClient_handleBufEnter(&params)?,
             NOTIFICATION__HandleCursorMoved => self.languageClient_handleCursorMoved(&params)?,
             NOTIFICATION__HandleCompleteDone => self.languageClient_handleCompleteDone(&params)?,
             NOTIFICATION__FZFSinkLocation => self.languageClient_FZFSinkLocation(&params)?,
@@ -174,7 +281,9 @@ impl LanguageClient {
             NOTIFICATION__RustBeginBuild => self.rust_handleBeginBuild(&params)?,
             NOTIFICATION__RustDiagnosticsBegin => self.rust_handleDiagnosticsBegin(&params)?,
             NOTIFICATION__RustDiagnosticsEnd => self.rust_handleDiagnosticsEnd(&params)?,
-            NOTIFICATION__WindowProgress => self.window_progress(&params)?,
+            NOTIFICATION__WindowProgress => {
+                self.window_progress(languageId.unwrap_or_default(), &params)?
+            }
             NOTIFICATION__ServerExited => self.languageClient_serverExited(&params)?,
 
             _ => {
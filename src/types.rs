@@ -29,13 +29,22 @@ pub const REQUEST__RegisterHandlers: &str = "languageClient/registerHandlers";
 pub const REQUEST__NCMRefresh: &str = "LanguageClient_NCMRefresh";
 pub const REQUEST__NCM2OnComplete: &str = "LanguageClient_NCM2OnComplete";
 pub const REQUEST__ExplainErrorAtPoint: &str = "languageClient/explainErrorAtPoint";
+pub const REQUEST__GotoRelatedInformation: &str = "languageClient/gotoRelatedInformation";
+pub const REQUEST__ExportDiagnostics: &str = "languageClient/exportDiagnostics";
+pub const REQUEST__GotoDiagnostic: &str = "languageClient/gotoDiagnostic";
+pub const REQUEST__WriteDiagnosticsBaseline: &str = "languageClient/writeDiagnosticsBaseline";
 pub const REQUEST__FindLocations: &str = "languageClient/findLocations";
+pub const REQUEST__CallHierarchy: &str = "languageClient/callHierarchy";
+pub const REQUEST__CallHierarchyToggle: &str = "languageClient/callHierarchyToggle";
+pub const REQUEST__CallHierarchyJump: &str = "languageClient/callHierarchyJump";
 pub const REQUEST__DebugInfo: &str = "languageClient/debugInfo";
+pub const REQUEST__HandleBufWritePre: &str = "languageClient/handleBufWritePre";
 pub const NOTIFICATION__HandleBufNewFile: &str = "languageClient/handleBufNewFile";
 pub const NOTIFICATION__HandleFileType: &str = "languageClient/handleFileType";
 pub const NOTIFICATION__HandleTextChanged: &str = "languageClient/handleTextChanged";
 pub const NOTIFICATION__HandleBufWritePost: &str = "languageClient/handleBufWritePost";
 pub const NOTIFICATION__HandleBufDelete: &str = "languageClient/handleBufDelete";
+pub const NOTIFICATION__HandleBufEnter: &str = "languageClient/handleBufEnter";
 pub const NOTIFICATION__HandleCursorMoved: &str = "languageClient/handleCursorMoved";
 pub const NOTIFICATION__HandleCompleteDone: &str = "languageClient/handleCompleteDone";
 pub const NOTIFICATION__FZFSinkLocation: &str = "LanguageClient_FZFSinkLocation";
@@ -52,6 +61,16 @@ pub const NOTIFICATION__RustDiagnosticsEnd: &str = "rustDocument/diagnosticsEnd"
 pub const NOTIFICATION__WindowProgress: &str = "window/progress";
 pub const NOTIFICATION__LanguageStatus: &str = "language/status";
 pub const REQUEST__ClassFileContents: &str = "java/classFileContents";
+pub const REQUEST__CSharpMetadata: &str = "csharp/metadata";
+pub const REQUEST__DenoVirtualTextDocument: &str = "deno/virtualTextDocument";
+// Pull diagnostics, not in lsp-types yet.
+pub const REQUEST__DocumentDiagnostic: &str = "textDocument/diagnostic";
+pub const REQUEST__WorkspaceDiagnostic: &str = "workspace/diagnostic";
+pub const REQUEST__WorkspaceDiagnosticRefresh: &str = "workspace/diagnostic/refresh";
+// Call hierarchy, not in lsp-types yet.
+pub const REQUEST__PrepareCallHierarchy: &str = "textDocument/prepareCallHierarchy";
+pub const REQUEST__CallHierarchyIncomingCalls: &str = "callHierarchy/incomingCalls";
+pub const REQUEST__CallHierarchyOutgoingCalls: &str = "callHierarchy/outgoingCalls";
 
 pub const CommandsClient: &[&str] = &["java.apply.workspaceEdit"];
 
@@ -87,6 +106,29 @@ pub enum Call {
     Notification(LanguageId, rpc::Notification),
 }
 
+/// Editor round-trips taken to handle messages of one method from vim.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RoundTripMetrics {
+    pub messages: u64,
+    /// Round-trips to prefetch the context of messages, at most one per message.
+    pub prefetch_round_trips: u64,
+    /// All round-trips, including prefetching.
+    pub round_trips: u64,
+    pub max_round_trips: u64,
+    /// Round-trips handlers would have taken without prefetching.
+    pub unprefetched_round_trips: u64,
+}
+
+impl RoundTripMetrics {
+    pub fn record(&mut self, prefetch_round_trips: u64, round_trips: u64, saved_round_trips: u64) {
+        self.messages += 1;
+        self.unprefetched_round_trips += round_trips - prefetch_round_trips + saved_round_trips;
+        self.prefetch_round_trips += prefetch_round_trips;
+        self.round_trips += round_trips;
+        self.max_round_trips = self.max_round_trips.max(round_trips);
+    }
+}
+
 #[derive(Clone, Copy, Serialize)]
 pub struct HighlightSource {
     pub buffer: u64,
@@ -106,24 +148,54 @@ pub struct State {
     pub vim: Vim,
 
     pub capabilities: HashMap<String, Value>,
+    pub position_encodings: HashMap<String, PositionEncoding>,
     pub registrations: Vec<Registration>,
     pub roots: HashMap<String, String>,
+    pub server_states: HashMap<String, ServerState>,
+    // languageId => progress id => description.
+    pub server_progress: HashMap<String, HashMap<String, String>>,
     pub text_documents: HashMap<String, TextDocumentItem>,
     pub text_documents_metadata: HashMap<String, TextDocumentItemMetadata>,
-    // filename => diagnostics.
+    pub document_formats: HashMap<String, DocumentFormat>,
+    #[serde(skip_serializing)]
+    pub did_change_lock: Arc<Mutex<()>>,
+    // filename => diagnostics, merged from all servers, in editor columns.
     pub diagnostics: HashMap<String, Vec<Diagnostic>>,
+    // filename => displays of each of `diagnostics`, by the filter of the server publishing it.
+    pub diagnostics_displays: HashMap<String, Vec<DiagnosticDisplays>>,
+    // languageId => filename => diagnostics published by the server.
+    pub server_diagnostics: HashMap<String, HashMap<String, Vec<Diagnostic>>>,
+    // project root => baseline.
+    #[serde(skip_serializing)]
+    pub diagnostics_baselines: HashMap<String, DiagnosticsBaseline>,
+    // languageId => uri => result id of pulled diagnostics.
+    pub diagnostics_result_ids: HashMap<String, HashMap<String, String>>,
+    // uri => diagnostics pulls of an open document.
+    pub diagnostics_pulls: HashMap<String, DocumentPull>,
+    // languageId of servers with a workspace diagnostics pull in flight.
+    pub workspace_diagnostics_pulls: HashSet<String>,
+    // languageId => filename => diagnostic code => link to its description.
+    pub code_descriptions: HashMap<String, HashMap<String, HashMap<String, String>>>,
     #[serde(skip_serializing)]
     pub line_diagnostics: HashMap<(String, u64), String>,
     pub signs: HashMap<String, Vec<Sign>>,
     pub signs_placed: HashMap<String, Vec<Sign>>,
+    // filename => next sign id.
+    pub sign_ids: HashMap<String, u64>,
+    pub sign_groups: bool,
     pub namespace_id: Option<i64>,
     pub highlight_source: Option<u64>,
     pub highlights: HashMap<String, Vec<Highlight>>,
     pub highlights_placed: HashMap<String, Vec<Highlight>>,
+    // Hidden buffers, whose diagnostics are shown once entered.
+    pub diagnostics_pending: HashSet<String>,
     // TODO: make file specific.
     pub highlight_match_ids: Vec<u32>,
     pub document_highlight_source: Option<HighlightSource>,
     pub user_handlers: HashMap<String, String>,
+    // Shown in the call hierarchy buffer.
+    #[serde(skip_serializing)]
+    pub call_hierarchy: Option<CallHierarchy>,
     #[serde(skip_serializing)]
     pub watchers: HashMap<String, notify::RecommendedWatcher>,
     #[serde(skip_serializing)]
@@ -131,9 +203,11 @@ pub struct State {
 
     pub is_nvim: bool,
     pub last_cursor_line: u64,
+    pub last_virtual_text_line: u64,
     pub last_line_diagnostic: String,
-    pub stashed_codeAction_commands: Vec<Command>,
+    pub stashed_codeAction_actions: Vec<CodeActionOrCommand>,
     pub viewport: Viewport,
+    pub round_trip_metrics: HashMap<String, RoundTripMetrics>,
 
     // User settings.
     pub serverCommands: HashMap<String, Vec<String>>,
@@ -144,10 +218,14 @@ pub struct State {
     pub diagnosticsEnable: bool,
     pub diagnosticsList: DiagnosticsList,
     pub diagnosticsDisplay: HashMap<u64, DiagnosticsDisplay>,
+    pub diagnosticsFilters: HashMap<String, DiagnosticsFilter>,
     pub diagnosticsSignsMax: Option<u64>,
+    pub diagnosticsSignsPriority: u64,
+    pub diagnosticsSignsCombine: bool,
     pub documentHighlightDisplay: HashMap<u64, DocumentHighlightDisplay>,
     pub windowLogMessageLevel: MessageType,
     pub settingsPath: String,
+    pub diagnosticsBaselinePath: String,
     pub loadSettings: bool,
     pub rootMarkers: Option<RootMarkers>,
     pub change_throttle: Option<Duration>,
@@ -155,6 +233,14 @@ pub struct State {
     pub hoverPreview: HoverPreviewOption,
     pub completionPreferTextEdit: bool,
     pub use_virtual_text: bool,
+    pub virtualTextPrefix: String,
+    pub virtualTextFormat: String,
+    pub virtualTextMaxLength: Option<u64>,
+    pub virtualTextHighestSeverityOnly: bool,
+    pub virtualTextCurrentLineOnly: bool,
+    pub preWriteActions: HashMap<String, Vec<String>>,
+    pub pre_write_timeout: Duration,
+    pub virtualDocumentMethods: HashMap<String, String>,
 
     pub loggingFile: Option<String>,
     pub loggingLevel: log::LevelFilter,
@@ -186,29 +272,47 @@ impl State {
             vim: Vim::new(client),
 
             capabilities: HashMap::new(),
+            position_encodings: HashMap::new(),
             registrations: vec![],
             roots: HashMap::new(),
+            server_states: HashMap::new(),
+            server_progress: HashMap::new(),
             text_documents: HashMap::new(),
             text_documents_metadata: HashMap::new(),
+            document_formats: HashMap::new(),
+            did_change_lock: Arc::new(Mutex::new(())),
             diagnostics: HashMap::new(),
+            diagnostics_displays: HashMap::new(),
+            server_diagnostics: HashMap::new(),
+            diagnostics_baselines: HashMap::new(),
+            diagnostics_result_ids: HashMap::new(),
+            diagnostics_pulls: HashMap::new(),
+            workspace_diagnostics_pulls: HashSet::new(),
+            code_descriptions: HashMap::new(),
             line_diagnostics: HashMap::new(),
             signs: HashMap::new(),
             signs_placed: HashMap::new(),
+            sign_ids: HashMap::new(),
+            sign_groups: false,
             namespace_id: None,
             highlight_source: None,
             highlights: HashMap::new(),
             highlights_placed: HashMap::new(),
+            diagnostics_pending: HashSet::new(),
             highlight_match_ids: Vec::new(),
             document_highlight_source: None,
             user_handlers: HashMap::new(),
+            call_hierarchy: None,
             watchers: HashMap::new(),
             watcher_rxs: HashMap::new(),
 
             is_nvim: false,
             last_cursor_line: 0,
+            last_virtual_text_line: 0,
             last_line_diagnostic: " ".into(),
-            stashed_codeAction_commands: vec![],
+            stashed_codeAction_actions: vec![],
             viewport: Viewport::new(0, 0),
+            round_trip_metrics: HashMap::new(),
 
             serverCommands: HashMap::new(),
             autoStart: true,
@@ -218,10 +322,14 @@ impl State {
             diagnosticsEnable: true,
             diagnosticsList: DiagnosticsList::Quickfix,
             diagnosticsDisplay: DiagnosticsDisplay::default(),
+            diagnosticsFilters: HashMap::new(),
             diagnosticsSignsMax: None,
+            diagnosticsSignsPriority: 10,
+            diagnosticsSignsCombine: false,
             documentHighlightDisplay: DocumentHighlightDisplay::default(),
             windowLogMessageLevel: MessageType::Warning,
             settingsPath: format!(".vim{}settings.json", std::path::MAIN_SEPARATOR),
+            diagnosticsBaselinePath: ".vim/diagnostics-baseline.json".to_owned(),
             loadSettings: false,
             rootMarkers: None,
             change_throttle: None,
@@ -229,6 +337,18 @@ impl State {
             hoverPreview: HoverPreviewOption::default(),
             completionPreferTextEdit: false,
             use_virtual_text: true,
+            virtualTextPrefix: String::new(),
+            virtualTextFormat: "{message}".to_owned(),
+            virtualTextMaxLength: None,
+            virtualTextHighestSeverityOnly: false,
+            virtualTextCurrentLineOnly: false,
+            preWriteActions: HashMap::new(),
+            pre_write_timeout: Duration::from_secs(1),
+            virtualDocumentMethods: hashmap! {
+                "jdt".to_owned() => REQUEST__ClassFileContents.to_owned(),
+                "csharp".to_owned() => REQUEST__CSharpMetadata.to_owned(),
+                "deno".to_owned() => REQUEST__DenoVirtualTextDocument.to_owned(),
+            },
             loggingFile: None,
             loggingLevel: log::LevelFilter::Warn,
             serverStderr: None,
@@ -290,6 +410,126 @@ impl FromStr for HoverPreviewOption {
     }
 }
 
+/// How `Position.character` counts characters, as negotiated with the language server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PositionEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16")]
+    Utf16,
+    #[serde(rename = "utf-32")]
+    Utf32,
+}
+
+impl Default for PositionEncoding {
+    fn default() -> Self {
+        PositionEncoding::Utf16
+    }
+}
+
+/// Line ending style of a file, as in vim's 'fileformat'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineEnding {
+    #[serde(rename = "unix")]
+    Unix,
+    #[serde(rename = "dos")]
+    Dos,
+    #[serde(rename = "mac")]
+    Mac,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        LineEnding::Unix
+    }
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Dos => "\r\n",
+            LineEnding::Mac => "\r",
+        }
+    }
+}
+
+/// How a buffer is written to disk.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentFormat {
+    #[serde(rename = "fileformat")]
+    pub line_ending: LineEnding,
+    /// Value of 'fileencoding', empty when the same as 'encoding'.
+    #[serde(rename = "fileencoding")]
+    pub encoding: String,
+    #[serde(rename = "endofline")]
+    pub end_of_line: bool,
+    #[serde(rename = "fixendofline")]
+    pub fix_end_of_line: bool,
+}
+
+impl DocumentFormat {
+    /// Whether the last line is terminated by a line ending when written.
+    pub fn has_final_line_ending(&self) -> bool {
+        self.end_of_line || self.fix_end_of_line
+    }
+
+    /// Text of buffer lines, exactly as written to disk.
+    pub fn join(&self, lines: &[String]) -> String {
+        if lines.is_empty() || lines == [""] {
+            return String::new();
+        }
+
+        let line_ending = self.line_ending.as_str();
+        let mut text = lines.join(line_ending);
+        if self.has_final_line_ending() {
+            text += line_ending;
+        }
+        text
+    }
+
+    /// Split text into buffer lines. Returns the lines and whether the last line is terminated.
+    pub fn split(&self, text: &str) -> (Vec<String>, bool) {
+        let mut lines: Vec<String> = text
+            .split(self.line_ending.as_str())
+            .map(ToOwned::to_owned)
+            .collect();
+        let terminated = lines.len() > 1 && lines.last().map(String::is_empty) == Some(true);
+        if terminated {
+            lines.pop();
+        }
+        (lines, terminated)
+    }
+
+    /// Replace line endings in text inserted into the buffer with `\n`.
+    pub fn normalize(&self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n");
+        if self.line_ending == LineEnding::Mac {
+            text.replace('\r', "\n")
+        } else {
+            text
+        }
+    }
+}
+
+#[test]
+fn test_document_format() {
+    let format = DocumentFormat {
+        line_ending: LineEnding::Dos,
+        end_of_line: true,
+        ..DocumentFormat::default()
+    };
+    let lines = vec!["a".to_owned(), "".to_owned()];
+    assert_eq!(format.join(&lines), "a\r\n\r\n");
+    assert_eq!(format.split("a\r\n\r\n"), (lines, true));
+    assert_eq!(format.normalize("b\r\nc\n"), "b\nc\n");
+
+    let format = DocumentFormat::default();
+    assert_eq!(format.join(&["a".to_owned()]), "a");
+    assert_eq!(format.split("a"), (vec!["a".to_owned()], false));
+    assert_eq!(format.join(&["".to_owned()]), "");
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum DiagnosticsList {
     Quickfix,
@@ -372,33 +612,537 @@ impl DiagnosticsDisplay {
     }
 }
 
+/// Regular expression, compiled once when read from settings.
+#[derive(Debug, Clone)]
+pub struct Pattern(regex::Regex);
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        Ok(Pattern(regex::Regex::new(s)?))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Filter of diagnostics, see `g:LanguageClient_diagnosticsFilters`. Severities are thresholds,
+/// i.e., the least severe severity kept, e.g., 2 keeps errors and warnings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsFilter {
+    pub severity: Option<u64>,
+    /// Sources to keep, all if not set.
+    pub sources: Option<Vec<String>>,
+    pub excludeSources: Vec<String>,
+    /// Patterns of codes and messages of diagnostics to keep, all if not set.
+    pub code: Option<Pattern>,
+    pub message: Option<Pattern>,
+    /// Patterns of codes and messages of diagnostics to drop.
+    pub excludeCode: Option<Pattern>,
+    pub excludeMessage: Option<Pattern>,
+
+    // Thresholds per display.
+    pub signs: Option<u64>,
+    pub highlights: Option<u64>,
+    pub virtualText: Option<u64>,
+    pub list: Option<u64>,
+}
+
+impl DiagnosticsFilter {
+    /// Diagnostics passing the filter. Display thresholds are not considered here.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter(|dn| {
+                if !Self::shows(self.severity, dn) {
+                    return false;
+                }
+
+                let source = dn.source.as_ref();
+                if let Some(ref sources) = self.sources {
+                    if !source.iter().any(|s| sources.contains(s)) {
+                        return false;
+                    }
+                }
+                if source.iter().any(|s| self.excludeSources.contains(s)) {
+                    return false;
+                }
+
+                let code = dn.code.as_ref().map(ToString::to_string);
+                if let Some(ref re) = self.code {
+                    if !code.as_ref().map_or(false, |code| re.is_match(code)) {
+                        return false;
+                    }
+                }
+                if let Some(ref re) = self.message {
+                    if !re.is_match(&dn.message) {
+                        return false;
+                    }
+                }
+                if let (Some(re), Some(code)) = (&self.excludeCode, &code) {
+                    if re.is_match(code) {
+                        return false;
+                    }
+                }
+                if let Some(ref re) = self.excludeMessage {
+                    if re.is_match(&dn.message) {
+                        return false;
+                    }
+                }
+
+                true
+            })
+            .collect()
+    }
+
+    /// Displays the diagnostic is shown in, by the thresholds of this filter.
+    pub fn displays(&self, diagnostic: &Diagnostic) -> DiagnosticDisplays {
+        DiagnosticDisplays {
+            signs: Self::shows(self.signs, diagnostic),
+            highlights: Self::shows(self.highlights, diagnostic),
+            virtualText: Self::shows(self.virtualText, diagnostic),
+            list: Self::shows(self.list, diagnostic),
+        }
+    }
+
+    /// Whether diagnostic is shown in a display with the threshold.
+    pub fn shows(threshold: Option<u64>, diagnostic: &Diagnostic) -> bool {
+        let severity = diagnostic.severity.unwrap_or(DiagnosticSeverity::Hint) as u64;
+        match threshold {
+            Some(threshold) => severity <= threshold,
+            None => true,
+        }
+    }
+}
+
+#[test]
+fn test_diagnostics_filter() {
+    let diagnostic = |severity, source: &str, code: &str, message: &str| Diagnostic {
+        severity: Some(severity),
+        source: Some(source.to_owned()),
+        code: Some(NumberOrString::String(code.to_owned())),
+        message: message.to_owned(),
+        ..Diagnostic::default()
+    };
+    let diagnostics = vec![
+        diagnostic(
+            DiagnosticSeverity::Error,
+            "rustc",
+            "E0308",
+            "mismatched types",
+        ),
+        diagnostic(DiagnosticSeverity::Warning, "rustc", "", "unused variable"),
+        diagnostic(
+            DiagnosticSeverity::Hint,
+            "clippy",
+            "needless_return",
+            "unneeded return",
+        ),
+    ];
+    let messages = |filter: DiagnosticsFilter| -> Vec<String> {
+        filter
+            .apply(diagnostics.clone())
+            .into_iter()
+            .map(|dn| dn.message)
+            .collect()
+    };
+
+    assert_eq!(messages(DiagnosticsFilter::default()).len(), 3);
+    assert_eq!(
+        messages(DiagnosticsFilter {
+            severity: Some(2),
+            ..DiagnosticsFilter::default()
+        }),
+        vec!["mismatched types", "unused variable"]
+    );
+    assert_eq!(
+        messages(DiagnosticsFilter {
+            sources: Some(vec!["clippy".to_owned()]),
+            ..DiagnosticsFilter::default()
+        }),
+        vec!["unneeded return"]
+    );
+    assert_eq!(
+        messages(DiagnosticsFilter {
+            excludeSources: vec!["clippy".to_owned()],
+            excludeCode: Some("^E03".parse().unwrap()),
+            ..DiagnosticsFilter::default()
+        }),
+        vec!["unused variable"]
+    );
+    assert_eq!(
+        messages(DiagnosticsFilter {
+            excludeMessage: Some("^unused".parse().unwrap()),
+            ..DiagnosticsFilter::default()
+        }),
+        vec!["mismatched types", "unneeded return"]
+    );
+    assert_eq!(
+        messages(DiagnosticsFilter {
+            code: Some("^E".parse().unwrap()),
+            ..DiagnosticsFilter::default()
+        }),
+        vec!["mismatched types"]
+    );
+    assert_eq!(
+        messages(DiagnosticsFilter {
+            message: Some("un".parse().unwrap()),
+            excludeMessage: Some("return".parse().unwrap()),
+            ..DiagnosticsFilter::default()
+        }),
+        vec!["unused variable"]
+    );
+
+    assert!(DiagnosticsFilter::shows(None, &diagnostics[2]));
+    assert!(!DiagnosticsFilter::shows(Some(1), &diagnostics[1]));
+    assert_eq!(
+        DiagnosticsFilter {
+            signs: Some(1),
+            list: Some(2),
+            ..DiagnosticsFilter::default()
+        }
+        .displays(&diagnostics[1]),
+        DiagnosticDisplays {
+            signs: false,
+            highlights: true,
+            virtualText: true,
+            list: true,
+        }
+    );
+    assert!(serde_json::from_value::<DiagnosticsFilter>(json!({"excludeMessage": "("})).is_err());
+}
+
+/// Displays a diagnostic is shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DiagnosticDisplays {
+    pub signs: bool,
+    pub highlights: bool,
+    pub virtualText: bool,
+    pub list: bool,
+}
+
+/// Diagnostic in a baseline, see `g:LanguageClient_diagnosticsBaselinePath`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    /// Relative to project root.
+    pub path: String,
+    /// 1-based.
+    pub line: u64,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// Pre-existing diagnostics of a project, which are not displayed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsBaseline {
+    pub diagnostics: Vec<BaselineEntry>,
+}
+
+impl DiagnosticsBaseline {
+    /// Create baseline from diagnostics of files, given as (path, diagnostics).
+    pub fn new(files: &[(String, Vec<Diagnostic>)]) -> Self {
+        let mut diagnostics: Vec<_> = files
+            .iter()
+            .flat_map(|(path, diagnostics)| {
+                diagnostics.iter().map(move |dn| BaselineEntry {
+                    path: path.clone(),
+                    line: dn.range.start.line + 1,
+                    code: dn.code.as_ref().map(ToString::to_string),
+                    message: dn.message.clone(),
+                })
+            })
+            .collect();
+        diagnostics
+            .sort_by(|a, b| (&a.path, a.line, &a.message).cmp(&(&b.path, b.line, &b.message)));
+        DiagnosticsBaseline { diagnostics }
+    }
+
+    /// Read baseline from file, or an empty one if there is no such file.
+    pub fn read(path: &Path) -> Fallible<Self> {
+        if !path.exists() {
+            return Ok(DiagnosticsBaseline::default());
+        }
+
+        let buffer = read_to_string(path).with_context(|err| {
+            format!("Failed to read file ({}): {}", path.to_string_lossy(), err)
+        })?;
+        Ok(serde_json::from_str(&buffer).with_context(|err| {
+            format!("Failed to parse file ({}): {}", path.to_string_lossy(), err)
+        })?)
+    }
+
+    pub fn write(&self, path: &Path) -> Fallible<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?).with_context(|err| {
+            format!("Failed to write file ({}): {}", path.to_string_lossy(), err)
+        })?;
+        Ok(())
+    }
+
+    /// Whether each diagnostic of a file is in the baseline, i.e., with same code and message.
+    /// Lines are matched fuzzily, as code might have been added or removed above: each baseline
+    /// entry suppresses one diagnostic, the closest one first.
+    pub fn suppressed(&self, path: &str, diagnostics: &[Diagnostic]) -> Vec<bool> {
+        let entries: Vec<_> = self
+            .diagnostics
+            .iter()
+            .filter(|entry| entry.path == path)
+            .collect();
+
+        let mut pairs = vec![];
+        for (i, dn) in diagnostics.iter().enumerate() {
+            let code = dn.code.as_ref().map(ToString::to_string);
+            for (j, entry) in entries.iter().enumerate() {
+                if entry.code == code && entry.message == dn.message {
+                    let line = dn.range.start.line + 1;
+                    let distance = line.max(entry.line) - line.min(entry.line);
+                    pairs.push((distance, i, j));
+                }
+            }
+        }
+        pairs.sort();
+
+        let mut suppressed = vec![false; diagnostics.len()];
+        let mut used = vec![false; entries.len()];
+        for (_, i, j) in pairs {
+            if !suppressed[i] && !used[j] {
+                suppressed[i] = true;
+                used[j] = true;
+            }
+        }
+        suppressed
+    }
+}
+
+#[test]
+fn test_diagnostics_baseline() {
+    let diagnostic = |line, message: &str| Diagnostic {
+        range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+        code: Some(NumberOrString::String("W1".to_owned())),
+        message: message.to_owned(),
+        ..Diagnostic::default()
+    };
+    let baseline = DiagnosticsBaseline::new(&[(
+        "src/a.rs".to_owned(),
+        vec![
+            diagnostic(20, "unused variable"),
+            diagnostic(10, "unused variable"),
+            diagnostic(30, "dead code"),
+        ],
+    )]);
+    assert_eq!(
+        baseline.diagnostics[0],
+        BaselineEntry {
+            path: "src/a.rs".to_owned(),
+            line: 11,
+            code: Some("W1".to_owned()),
+            message: "unused variable".to_owned(),
+        }
+    );
+
+    // Two lines added at top, and a new unused variable in between.
+    let diagnostics = vec![
+        diagnostic(12, "unused variable"),
+        diagnostic(15, "unused variable"),
+        diagnostic(22, "unused variable"),
+        diagnostic(32, "dead code"),
+    ];
+    assert_eq!(
+        baseline.suppressed("src/a.rs", &diagnostics),
+        vec![true, false, true, true]
+    );
+    assert_eq!(
+        baseline.suppressed("src/b.rs", &diagnostics),
+        vec![false; 4]
+    );
+
+    let path = std::env::temp_dir()
+        .join(format!("LanguageClient-test-{}", std::process::id()))
+        .join(".vim/diagnostics-baseline.json");
+    assert_eq!(
+        DiagnosticsBaseline::read(&path).unwrap(),
+        DiagnosticsBaseline::default()
+    );
+    baseline.write(&path).unwrap();
+    assert_eq!(DiagnosticsBaseline::read(&path).unwrap(), baseline);
+    std::fs::remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerState {
+    Starting,
+    Running,
+    Crashed,
+}
+
+/// Diagnostics pulls of an open document. At most one pull is in flight, and results of
+/// documents changed in the meantime are dropped, as another pull follows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DocumentPull {
+    /// Number of pulls requested, i.e., of changes of the document.
+    pub version: u64,
+    pub in_flight: bool,
+    /// Whether to pull again once the pull in flight is done.
+    pub pending: bool,
+}
+
+impl DocumentPull {
+    /// Request a pull as the document changed. Returns whether to start one, i.e., none is in
+    /// flight.
+    pub fn request(&mut self) -> bool {
+        self.version += 1;
+        if self.in_flight {
+            self.pending = true;
+            return false;
+        }
+        self.in_flight = true;
+        true
+    }
+
+    /// Finish the pull in flight. Returns whether to pull again, as the document changed in the
+    /// meantime.
+    pub fn finish(&mut self) -> bool {
+        if self.pending {
+            self.pending = false;
+            return true;
+        }
+        self.in_flight = false;
+        false
+    }
+}
+
+#[test]
+fn test_document_pull() {
+    let mut pull = DocumentPull::default();
+    assert!(pull.request());
+    let version = pull.version;
+    // Changes while in flight are pulled once, after the pull in flight, whose result is
+    // outdated.
+    assert!(!pull.request());
+    assert!(!pull.request());
+    assert_ne!(pull.version, version);
+    assert!(pull.finish());
+    let version = pull.version;
+    assert!(!pull.finish());
+    assert_eq!(pull.version, version);
+    assert!(!pull.in_flight);
+    assert!(pull.request());
+}
+
+/// Number of diagnostics per severity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsCounts {
+    pub error: u64,
+    pub warning: u64,
+    pub information: u64,
+    pub hint: u64,
+}
+
+impl DiagnosticsCounts {
+    pub fn new(diagnostics: &[Diagnostic]) -> Self {
+        let mut counts = Self::default();
+        for dn in diagnostics {
+            match dn.severity.unwrap_or(DiagnosticSeverity::Hint) {
+                DiagnosticSeverity::Error => counts.error += 1,
+                DiagnosticSeverity::Warning => counts.warning += 1,
+                DiagnosticSeverity::Information => counts.information += 1,
+                DiagnosticSeverity::Hint => counts.hint += 1,
+            }
+        }
+        counts
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.error += other.error;
+        self.warning += other.warning;
+        self.information += other.information;
+        self.hint += other.hint;
+    }
+}
+
+#[test]
+fn test_diagnostics_counts() {
+    let diagnostic = |severity| Diagnostic {
+        severity,
+        ..Diagnostic::default()
+    };
+    let mut counts = DiagnosticsCounts::new(&[
+        diagnostic(Some(DiagnosticSeverity::Error)),
+        diagnostic(Some(DiagnosticSeverity::Error)),
+        diagnostic(Some(DiagnosticSeverity::Information)),
+        diagnostic(None),
+    ]);
+    assert_eq!(
+        counts,
+        DiagnosticsCounts {
+            error: 2,
+            warning: 0,
+            information: 1,
+            hint: 1,
+        }
+    );
+
+    counts.add(&DiagnosticsCounts::new(&[diagnostic(Some(
+        DiagnosticSeverity::Warning,
+    ))]));
+    assert_eq!(counts.warning, 1);
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sign {
+    /// Allocated when placed.
     pub id: u64,
     pub line: u64,
     pub text: String,
     pub severity: Option<DiagnosticSeverity>,
+    /// Whether there are also diagnostics of lower severities on the line.
+    pub combined: bool,
 }
 
 impl Sign {
     pub fn new(line: u64, text: String, severity: Option<DiagnosticSeverity>) -> Sign {
         Sign {
-            id: Self::get_id(line, severity),
+            id: 0,
             line,
             text,
             severity,
+            combined: false,
         }
     }
 
-    fn get_id(line: u64, severity: Option<DiagnosticSeverity>) -> u64 {
-        let base_id = 75_000;
-        base_id
-            + (line - 1) * 4
-            + severity
-                .unwrap_or(DiagnosticSeverity::Hint)
-                .to_int()
-                .unwrap_or(4)
-            - 1
+    pub fn name(&self) -> String {
+        format!(
+            "LanguageClient{:?}{}",
+            self.severity.unwrap_or(DiagnosticSeverity::Hint),
+            if self.combined { "Combined" } else { "" }
+        )
     }
 }
 
@@ -438,7 +1182,8 @@ impl DocumentHighlightDisplay {
 
 impl std::cmp::Ord for Sign {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.id.cmp(&other.id)
+        let severity = |sign: &Self| sign.severity.unwrap_or(DiagnosticSeverity::Hint) as u64;
+        (self.line, severity(self)).cmp(&(other.line, severity(other)))
     }
 }
 
@@ -451,7 +1196,9 @@ impl std::cmp::PartialOrd for Sign {
 impl std::cmp::PartialEq for Sign {
     fn eq(&self, other: &Self) -> bool {
         // Quick check whether sign should be updated.
-        self.text == other.text && self.severity == other.severity
+        self.text == other.text
+            && self.severity == other.severity
+            && self.combined == other.combined
     }
 }
 
@@ -460,7 +1207,9 @@ impl std::cmp::Eq for Sign {}
 use std::hash::{Hash, Hasher};
 impl Hash for Sign {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.id.hash(state);
+        self.text.hash(state);
+        self.severity.map(|severity| severity as u64).hash(state);
+        self.combined.hash(state);
     }
 }
 
@@ -476,7 +1225,7 @@ pub struct Highlight {
 impl PartialEq for Highlight {
     fn eq(&self, other: &Self) -> bool {
         // Quick check whether highlight should be updated.
-        self.text == other.text && self.group == other.group
+        self.line == other.line && self.text == other.text && self.group == other.group
     }
 }
 
@@ -506,6 +1255,14 @@ pub struct NCMInfo {
     pub sort: u64,
 }
 
+/// Window displaying a buffer, see `LSP#windows()`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BufferWindow {
+    pub bufnr: i64,
+    pub viewport: Viewport,
+    pub current: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NCMContext {
     pub bufnr: u64,
@@ -907,11 +1664,15 @@ pub enum VimVar {
     Character,
     Viewport,
     Text,
+    DocumentFormat,
     Cword,
     NewName,
     GotoCmd,
+    Peek,
     Handle,
     IncludeDeclaration,
+    TabSize,
+    InsertSpaces,
 }
 
 pub trait VimExp {
@@ -929,11 +1690,15 @@ impl VimExp for VimVar {
             VimVar::Character => "character",
             VimVar::Viewport => "viewport",
             VimVar::Text => "text",
+            VimVar::DocumentFormat => "documentFormat",
             VimVar::Cword => "cword",
             VimVar::NewName => "newName",
             VimVar::GotoCmd => "gotoCmd",
+            VimVar::Peek => "peek",
             VimVar::Handle => "handle",
             VimVar::IncludeDeclaration => "includeDeclaration",
+            VimVar::TabSize => "tabSize",
+            VimVar::InsertSpaces => "insertSpaces",
         }
         .to_owned()
     }
@@ -947,9 +1712,13 @@ impl VimExp for VimVar {
             VimVar::Character => "LSP#character()",
             VimVar::Viewport => "LSP#viewport()",
             VimVar::Text => "LSP#text()",
+            VimVar::DocumentFormat => "LSP#document_format()",
             VimVar::Cword => "expand('<cword>')",
             VimVar::NewName | VimVar::GotoCmd => "v:null",
+            VimVar::Peek => "0",
             VimVar::Handle | VimVar::IncludeDeclaration => "v:true",
+            VimVar::TabSize => "shiftwidth()",
+            VimVar::InsertSpaces => "&expandtab",
         }
         .to_owned()
     }
@@ -1022,6 +1791,7 @@ pub enum RootMarkers {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowProgressParams {
+    pub id: Option<String>,
     pub title: Option<String>,
     pub message: Option<String>,
     pub percentage: Option<f64>,
@@ -1040,14 +1810,22 @@ impl Filepath for Url {
 
 #[derive(Debug, Serialize)]
 pub struct TextDocumentItemMetadata {
+    /// When the last didChange was sent.
     #[serde(skip_serializing)]
     pub last_change: Instant,
+    /// When the buffer was last changed in the editor.
+    #[serde(skip_serializing)]
+    pub last_text_changed: Instant,
+    /// Whether a trailing didChange is scheduled.
+    pub flush_scheduled: bool,
 }
 
 impl Default for TextDocumentItemMetadata {
     fn default() -> Self {
         Self {
             last_change: Instant::now(),
+            last_text_changed: Instant::now(),
+            flush_scheduled: false,
         }
     }
 }
@@ -1141,9 +1919,107 @@ impl FromLSP<SymbolInformation> for QuickfixEntry {
     }
 }
 
+/// An entry of a `textDocument/codeAction` response, which is either a bare command or a code
+/// action literal.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CodeActionOrCommand {
+    Command(Command),
+    CodeAction(CodeAction),
+}
+
+impl CodeActionOrCommand {
+    /// Entry shown in selection UI, in the form of `command: title` or `kind: title`.
+    pub fn to_selection_entry(&self) -> String {
+        match *self {
+            CodeActionOrCommand::Command(ref cmd) => format!("{}: {}", cmd.command, cmd.title),
+            CodeActionOrCommand::CodeAction(ref action) => {
+                let kind = action
+                    .kind
+                    .as_deref()
+                    .or_else(|| action.command.as_ref().map(|cmd| cmd.command.as_str()))
+                    .unwrap_or("codeAction");
+                format!("{}: {}", kind, action.title)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Serialize)]
 pub struct VirtualText {
     pub line: u64,
     pub text: String,
     pub hl_group: String,
 }
+
+/// How diagnostics are rendered as virtual text.
+#[derive(Debug, Clone)]
+pub struct VirtualTextFormat {
+    pub prefix: String,
+    /// With placeholders `{message}`, `{severity}`, `{code}` and `{source}`.
+    pub template: String,
+    pub maxLength: Option<u64>,
+}
+
+impl VirtualTextFormat {
+    pub fn apply(&self, diagnostic: &Diagnostic, severity: &str) -> String {
+        let text = self
+            .template
+            .replace("{severity}", severity)
+            .replace(
+                "{code}",
+                &diagnostic
+                    .code
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+            )
+            .replace(
+                "{source}",
+                diagnostic.source.as_ref().map_or("", String::as_str),
+            )
+            // Last, as message might contain placeholders itself.
+            .replace("{message}", &diagnostic.message.replace("\n", "  "));
+        let text = self.prefix.clone() + &text;
+
+        match self.maxLength {
+            Some(maxLength) if text.chars().count() as u64 > maxLength => {
+                let mut text: String = text
+                    .chars()
+                    .take(maxLength.saturating_sub(1) as usize)
+                    .collect();
+                text.push('…');
+                text
+            }
+            _ => text,
+        }
+    }
+}
+
+#[test]
+fn test_virtual_text_format() {
+    let diagnostic = Diagnostic {
+        code: Some(NumberOrString::Number(2304)),
+        source: Some("ts".to_owned()),
+        message: "Cannot find name {code}.\nDid you mean 'foo'?".to_owned(),
+        ..Diagnostic::default()
+    };
+
+    let mut format = VirtualTextFormat {
+        prefix: String::new(),
+        template: "{message}".to_owned(),
+        maxLength: None,
+    };
+    assert_eq!(
+        format.apply(&diagnostic, "Error"),
+        "Cannot find name {code}.  Did you mean 'foo'?"
+    );
+
+    format.prefix = "■ ".to_owned();
+    format.template = "{severity}: {message} [{source} {code}]".to_owned();
+    format.maxLength = Some(24);
+    assert_eq!(
+        format.apply(&diagnostic, "Error"),
+        "■ Error: Cannot find na…"
+    );
+}
@@ -1,4 +1,5 @@
 use super::*;
+use crate::lsp::request::GotoDefinitionResponse;
 
 pub fn escape_single_quote<S: AsRef<str>>(s: S) -> String {
     s.as_ref().replace("'", "''")
@@ -128,6 +129,13 @@ impl<P: AsRef<Path> + std::fmt::Debug> ToUrl for P {
 }
 
 pub fn apply_TextEdits(lines: &[String], edits: &[TextEdit]) -> Fallible<Vec<String>> {
+    let text = apply_TextEdits_to_text(&lines.join("\n"), edits)?;
+    Ok(text.lines().map(|l| l.to_owned()).collect())
+}
+
+/// Apply edits to text, exactly, with lines split at `\n` and characters counted in bytes.
+pub fn apply_TextEdits_to_text(text: &str, edits: &[TextEdit]) -> Fallible<String> {
+    let lines: Vec<&str> = text.split('\n').collect();
     // Edits are ordered from bottom to top, from right to left.
     let mut edits_by_index = vec![];
     for edit in edits {
@@ -149,14 +157,14 @@ pub fn apply_TextEdits(lines: &[String], edits: &[TextEdit]) -> Fallible<Vec<Str
         edits_by_index.push((start, end, &edit.new_text));
     }
 
-    let mut text = lines.join("\n");
+    let mut text = text.to_owned();
     for (start, end, new_text) in edits_by_index {
         let start = std::cmp::min(start, text.len());
         let end = std::cmp::min(end, text.len());
         text = String::new() + &text[..start] + new_text + &text[end..];
     }
 
-    Ok(text.lines().map(|l| l.to_owned()).collect())
+    Ok(text)
 }
 
 #[test]
@@ -221,52 +229,621 @@ fn test_apply_TextEdit_overlong_end() {
     assert_eq!(apply_TextEdits(&lines, &[edit]).unwrap(), expect);
 }
 
-fn get_command_add_sign(sign: &Sign, filename: &str) -> String {
-    format!(
-        "sign place {} line={} name=LanguageClient{:?} file={}",
-        sign.id,
-        sign.line,
-        sign.severity.unwrap_or(DiagnosticSeverity::Hint),
-        filename
-    )
+#[test]
+fn test_apply_TextEdits_to_text() {
+    let edit = |start: (u64, u64), end: (u64, u64), new_text: &str| TextEdit {
+        range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+        new_text: new_text.to_owned(),
+    };
+
+    // Blank last line, before the final line ending.
+    assert_eq!(
+        apply_TextEdits_to_text("a\n\n", &[edit((1, 0), (2, 0), "b\nc\n")]).unwrap(),
+        "a\nb\nc\n"
+    );
+    // Final line ending removed.
+    assert_eq!(
+        apply_TextEdits_to_text("a\nb\n", &[edit((1, 1), (2, 0), "")]).unwrap(),
+        "a\nb"
+    );
+    assert_eq!(
+        apply_TextEdits_to_text("", &[edit((0, 0), (0, 0), "a\n")]).unwrap(),
+        "a\n"
+    );
+}
+
+/// Decode file content in given vim 'fileencoding'. Invalid or unsupported content is decoded
+/// lossily, as UTF-8.
+pub fn decode_text(bytes: &[u8], encoding: &str) -> String {
+    let decode_utf16 = |to_u16: fn([u8; 2]) -> u16| {
+        let units: Vec<_> = bytes
+            .chunks(2)
+            .filter(|chunk| chunk.len() == 2)
+            .map(|chunk| to_u16([chunk[0], chunk[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = match encoding.to_ascii_lowercase().as_str() {
+        "latin1" | "iso-8859-1" => bytes.iter().map(|&b| char::from(b)).collect(),
+        "utf-16" | "ucs-2" => decode_utf16(u16::from_be_bytes),
+        "utf-16le" | "ucs-2le" => decode_utf16(u16::from_le_bytes),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    text.trim_start_matches('\u{feff}').to_owned()
 }
 
 #[test]
-fn test_get_command_add_sign() {
-    let sign = Sign::new(1, "".to_owned(), Some(DiagnosticSeverity::Error));
+fn test_decode_text() {
+    assert_eq!(decode_text(b"caf\xc3\xa9", ""), "café");
+    assert_eq!(decode_text(b"\xef\xbb\xbfcaf\xc3\xa9", "utf-8"), "café");
+    assert_eq!(decode_text(b"caf\xe9", "latin1"), "café");
+    assert_eq!(decode_text(b"\xfe\xff\x00a\x00\xe9", "utf-16"), "aé");
+    assert_eq!(decode_text(b"a\x00\xe9\x00", "utf-16le"), "aé");
+    assert_eq!(decode_text(b"caf\xe9", "utf-8"), "caf\u{fffd}");
+}
+
+/// Split a range into single line ranges, i.e., `(line, character_start, character_end)`, with
+/// characters being byte columns of `lines`. Lines not in `lines` are skipped.
+pub fn split_range_by_line(range: &Range, lines: &[String]) -> Vec<(u64, u64, u64)> {
+    if lines.is_empty() {
+        return vec![];
+    }
+
+    // Whole file ranges might end at a huge line.
+    let last_line = range.end.line.min(lines.len() as u64 - 1);
+    (range.start.line..=last_line)
+        .filter_map(|line| {
+            let len = lines.get(line as usize)?.len() as u64;
+            let start = if line == range.start.line {
+                range.start.character.min(len)
+            } else {
+                0
+            };
+            let end = if line == range.end.line {
+                range.end.character.min(len)
+            } else {
+                len
+            };
+            // A range ending at the beginning of a line doesn't cover any of it.
+            if line != range.start.line && line == range.end.line && end == 0 {
+                return None;
+            }
+            Some((line, start, end))
+        })
+        .collect()
+}
+
+#[test]
+fn test_split_range_by_line() {
+    let lines: Vec<String> = vec!["fn main() {".into(), "    0".into(), "}".into()];
+    let range = |start: (u64, u64), end: (u64, u64)| Range {
+        start: Position::new(start.0, start.1),
+        end: Position::new(end.0, end.1),
+    };
+
+    assert_eq!(
+        split_range_by_line(&range((0, 3), (0, 7)), &lines),
+        vec![(0, 3, 7)]
+    );
+    assert_eq!(
+        split_range_by_line(&range((0, 10), (2, 1)), &lines),
+        vec![(0, 10, 11), (1, 0, 5), (2, 0, 1)]
+    );
+    assert_eq!(
+        split_range_by_line(&range((1, 4), (2, 0)), &lines),
+        vec![(1, 4, 5)]
+    );
+    assert_eq!(
+        split_range_by_line(&range((2, 0), (5, 0)), &lines),
+        vec![(2, 0, 1)]
+    );
+    assert_eq!(
+        split_range_by_line(&range((1, 0), (u64::from(u32::MAX), 0)), &lines),
+        vec![(1, 0, 5), (2, 0, 1)]
+    );
+    assert_eq!(
+        split_range_by_line(&range((0, 0), (u64::from(u32::MAX), 0)), &[]),
+        vec![]
+    );
+}
+
+/// Number of code units of a character in given position encoding.
+fn char_len(c: char, encoding: PositionEncoding) -> u64 {
+    match encoding {
+        PositionEncoding::Utf8 => c.len_utf8() as u64,
+        PositionEncoding::Utf16 => c.len_utf16() as u64,
+        PositionEncoding::Utf32 => 1,
+    }
+}
+
+/// Length of a string in code units of given position encoding.
+fn encoded_len(s: &str, encoding: PositionEncoding) -> u64 {
+    s.chars().map(|c| char_len(c, encoding)).sum()
+}
+
+/// Convert a byte offset into a line into an LSP character offset. Offsets past the end of the
+/// line are kept past the end of the line.
+pub fn byte_to_character(line: &str, byte: u64, encoding: PositionEncoding) -> u64 {
+    if encoding == PositionEncoding::Utf8 {
+        return byte;
+    }
+
+    let mut character = 0;
+    for (idx, c) in line.char_indices() {
+        if idx as u64 >= byte {
+            return character;
+        }
+        character += char_len(c, encoding);
+    }
+    character + byte.saturating_sub(line.len() as u64)
+}
+
+/// Convert an LSP character offset into a line into a byte offset. Offsets past the end of the
+/// line are kept past the end of the line.
+pub fn character_to_byte(line: &str, character: u64, encoding: PositionEncoding) -> u64 {
+    if encoding == PositionEncoding::Utf8 {
+        return character;
+    }
+
+    let mut units = 0;
+    for (idx, c) in line.char_indices() {
+        if units >= character {
+            return idx as u64;
+        }
+        units += char_len(c, encoding);
+    }
+    line.len() as u64 + character.saturating_sub(units)
+}
+
+#[test]
+fn test_position_encoding_conversion() {
+    let line = "a😀b";
+    assert_eq!(byte_to_character(line, 5, PositionEncoding::Utf16), 3);
+    assert_eq!(byte_to_character(line, 5, PositionEncoding::Utf32), 2);
+    assert_eq!(byte_to_character(line, 5, PositionEncoding::Utf8), 5);
+    assert_eq!(byte_to_character(line, 8, PositionEncoding::Utf16), 6);
+    assert_eq!(character_to_byte(line, 3, PositionEncoding::Utf16), 5);
+    assert_eq!(character_to_byte(line, 2, PositionEncoding::Utf32), 5);
+    assert_eq!(character_to_byte(line, 4, PositionEncoding::Utf16), 6);
+    assert_eq!(character_to_byte(line, 6, PositionEncoding::Utf16), 8);
+}
+
+/// Split text at line endings as the protocol does, i.e., at `\n`, `\r\n` and `\r`, into pairs of
+/// line content and line ending. The last line has no line ending.
+fn split_lines_with_endings(text: &str) -> Vec<(&str, &str)> {
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let end = match c {
+            '\n' => idx + 1,
+            '\r' if chars.peek().map(|&(_, c)| c) == Some('\n') => {
+                chars.next();
+                idx + 2
+            }
+            '\r' => idx + 1,
+            _ => continue,
+        };
+        lines.push((&text[start..idx], &text[idx..end]));
+        start = end;
+    }
+    lines.push((&text[start..], ""));
+    lines
+}
+
+/// Compute incremental content changes turning `old` text into `new` text.
+///
+/// Changes are line based and ordered top to bottom, with positions relative to the document
+/// after applying all the preceding changes. A change of a single line is narrowed down to the
+/// modified characters. Columns and lengths are counted in the negotiated position encoding.
+pub fn get_text_document_content_changes(
+    old: &str,
+    new: &str,
+    encoding: PositionEncoding,
+) -> Vec<TextDocumentContentChangeEvent> {
+    let units = |s: &str| encoded_len(s, encoding);
+    let old_lines = split_lines_with_endings(old);
+    let new_lines = split_lines_with_endings(new);
+
+    // Hunks of (old start line, number of deleted lines, new start line, inserted lines).
+    let mut hunks = vec![];
+    let mut hunk: Option<(usize, usize, usize, Vec<_>)> = None;
+    let (mut old_idx, mut new_idx) = (0, 0);
+    for comp in diff::slice(&old_lines, &new_lines) {
+        match comp {
+            diff::Result::Both(..) => {
+                hunks.extend(hunk.take());
+                old_idx += 1;
+                new_idx += 1;
+            }
+            diff::Result::Left(_) => {
+                hunk.get_or_insert((old_idx, 0, new_idx, vec![])).1 += 1;
+                old_idx += 1;
+            }
+            diff::Result::Right(&line) => {
+                hunk.get_or_insert((old_idx, 0, new_idx, vec![]))
+                    .3
+                    .push(line);
+                new_idx += 1;
+            }
+        }
+    }
+    hunks.extend(hunk.take());
+
+    let old_len = old_lines.len();
+    let join = |lines: &[(&str, &str)]| -> String {
+        lines
+            .iter()
+            .map(|(content, ending)| format!("{}{}", content, ending))
+            .collect()
+    };
+    hunks
+        .into_iter()
+        .map(|(old_start, deleted, new_start, inserted)| {
+            let line = new_start as u64;
+            let old_end = old_start + deleted;
+            let (range, removed, text) =
+                if deleted == 1 && inserted.len() == 1 && old_lines[old_start].1 == inserted[0].1 {
+                    let (old_line, new_line) = (old_lines[old_start].0, inserted[0].0);
+                    let prefix: String = old_line
+                        .chars()
+                        .zip(new_line.chars())
+                        .take_while(|(a, b)| a == b)
+                        .map(|(c, _)| c)
+                        .collect();
+                    let (old_rest, new_rest) =
+                        (&old_line[prefix.len()..], &new_line[prefix.len()..]);
+                    let suffix: String = old_rest
+                        .chars()
+                        .rev()
+                        .zip(new_rest.chars().rev())
+                        .take_while(|(a, b)| a == b)
+                        .map(|(c, _)| c)
+                        .collect();
+                    let removed = &old_rest[..old_rest.len() - suffix.len()];
+                    let text = &new_rest[..new_rest.len() - suffix.len()];
+                    let start = units(&prefix);
+                    (
+                        Range::new(
+                            Position::new(line, start),
+                            Position::new(line, start + units(removed)),
+                        ),
+                        removed.to_owned(),
+                        text.to_owned(),
+                    )
+                } else {
+                    let end = if old_end < old_len || deleted == 0 {
+                        Position::new(line + deleted as u64, 0)
+                    } else {
+                        // The last line has no line ending, so the hunk ends with its content.
+                        Position::new(line + deleted as u64 - 1, units(old_lines[old_len - 1].0))
+                    };
+                    (
+                        Range::new(Position::new(line, 0), end),
+                        join(&old_lines[old_start..old_end]),
+                        join(&inserted),
+                    )
+                };
+
+            TextDocumentContentChangeEvent {
+                range: Some(range),
+                range_length: Some(units(&removed)),
+                text,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_get_text_document_content_changes() {
+    let changes = get_text_document_content_changes(
+        "fn main() {\n    0;\n}",
+        "fn main() {\n    1;\n}",
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        changes,
+        vec![TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(1, 4), Position::new(1, 5))),
+            range_length: Some(1),
+            text: "1".to_owned(),
+        }]
+    );
+}
+
+#[test]
+fn test_get_text_document_content_changes_encoding() {
+    let change = |encoding| {
+        get_text_document_content_changes("a😀b\nx\nä😀", "a😀c\nx\nä", encoding)
+            .into_iter()
+            .map(|change| (change.range.unwrap(), change.range_length.unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let range = |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+    assert_eq!(
+        change(PositionEncoding::Utf8),
+        vec![(range(0, 5, 6), 1), (range(2, 2, 6), 4)]
+    );
+    assert_eq!(
+        change(PositionEncoding::Utf16),
+        vec![(range(0, 3, 4), 1), (range(2, 1, 3), 2)]
+    );
+    assert_eq!(
+        change(PositionEncoding::Utf32),
+        vec![(range(0, 2, 3), 1), (range(2, 1, 2), 1)]
+    );
+}
+
+#[test]
+fn test_get_text_document_content_changes_line_endings() {
+    let change = |old, new| {
+        get_text_document_content_changes(old, new, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|change| {
+                let range = change.range.unwrap();
+                (
+                    (range.start.line, range.start.character),
+                    (range.end.line, range.end.character),
+                    change.range_length.unwrap(),
+                    change.text,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Old mac line endings.
+    assert_eq!(
+        change("a\rb\rc", "a\rx\rc"),
+        vec![((1, 0), (1, 1), 1, "x".to_owned())]
+    );
+    assert_eq!(
+        change("a\rb", "a\rb\rc"),
+        vec![((1, 0), (1, 1), 1, "b\rc".to_owned())]
+    );
+    // Final line endings of dos files.
+    assert_eq!(
+        change("a\r\nb\r\n", "a\r\nb\r\nc\r\n"),
+        vec![((2, 0), (2, 0), 0, "c\r\n".to_owned())]
+    );
+    assert_eq!(
+        change("a\r\nb\r\n", "a\r\n"),
+        vec![((1, 0), (2, 0), 3, "".to_owned())]
+    );
+    assert_eq!(
+        change("a\r\nb\r\n", "a\r\nb"),
+        vec![((1, 0), (2, 0), 3, "b".to_owned())]
+    );
+}
+
+#[test]
+fn test_get_text_document_content_changes_roundtrip() {
+    /// Apply content changes the way a language server does, counting code units of the
+    /// position encoding.
+    fn apply(
+        text: &str,
+        changes: &[TextDocumentContentChangeEvent],
+        encoding: PositionEncoding,
+    ) -> String {
+        let mut text: Vec<char> = text.chars().collect();
+        for change in changes {
+            let offset = |text: &[char], pos: Position| -> usize {
+                let mut offset = 0;
+                let mut line = 0;
+                while line < pos.line {
+                    if text[offset] == '\r' && text.get(offset + 1) == Some(&'\n') {
+                        offset += 1;
+                    }
+                    if text[offset] == '\n' || text[offset] == '\r' {
+                        line += 1;
+                    }
+                    offset += 1;
+                }
+                let mut units = 0;
+                while units < pos.character {
+                    units += char_len(text[offset], encoding);
+                    offset += 1;
+                }
+                assert_eq!(units, pos.character, "position inside a character");
+                offset
+            };
+            let range = change.range.unwrap();
+            let start = offset(&text, range.start);
+            let end = offset(&text, range.end);
+            let removed: String = text[start..end].iter().collect();
+            assert_eq!(change.range_length, Some(encoded_len(&removed, encoding)));
+            text.splice(start..end, change.text.chars());
+        }
+        text.into_iter().collect()
+    }
+
+    // Deterministic xorshift generator, so that failures are reproducible.
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut rand = move |n: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % n as u64) as usize
+    };
+    let words = [
+        "", "a", "foo", "bär", "😀x", "\n", "\n\n", " ", "\r\n", "\r",
+    ];
+    let random_text = |rand: &mut dyn FnMut(usize) -> usize| -> String {
+        (0..rand(12)).map(|_| words[rand(words.len())]).collect()
+    };
+
+    let encodings = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Utf32,
+    ];
+    for i in 0..3000 {
+        let encoding = encodings[i % encodings.len()];
+        let old = random_text(&mut rand);
+        let mut new = old.clone();
+        for _ in 0..=rand(3) {
+            let mut at = rand(new.len() + 1);
+            while !new.is_char_boundary(at) {
+                at -= 1;
+            }
+            let mut end = std::cmp::min(new.len(), at + rand(4));
+            while !new.is_char_boundary(end) {
+                end += 1;
+            }
+            new.replace_range(at..end, &random_text(&mut rand));
+        }
+
+        let changes = get_text_document_content_changes(&old, &new, encoding);
+        assert_eq!(
+            apply(&old, &changes, encoding),
+            new,
+            "old: {:?}, changes: {:?}, encoding: {:?}",
+            old,
+            changes,
+            encoding
+        );
+    }
+}
+
+/// Get signs of diagnostics, one per line, of the highest severity.
+pub fn get_signs(diagnostics: &[&Diagnostic], lines: &[String], combine: bool) -> Vec<Sign> {
+    let mut diagnostics = diagnostics.to_vec();
+    diagnostics.sort_by_key(|dn| {
+        (
+            dn.range.start.line,
+            dn.severity.unwrap_or(DiagnosticSeverity::Hint) as u64,
+        )
+    });
+
+    let mut signs: Vec<Sign> = vec![];
+    for dn in diagnostics {
+        let line = dn.range.start.line + 1;
+        if let Some(sign) = signs.last_mut() {
+            if sign.line == line {
+                sign.combined |= combine && sign.severity != dn.severity;
+                continue;
+            }
+        }
+
+        let text = lines
+            .get(dn.range.start.line as usize)
+            .cloned()
+            .unwrap_or_default();
+        signs.push(Sign::new(line, text, dn.severity));
+    }
+    signs
+}
+
+#[test]
+fn test_get_signs() {
+    let diagnostic = |line, severity| Diagnostic {
+        range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+        severity: Some(severity),
+        ..Diagnostic::default()
+    };
+    let diagnostics = [
+        diagnostic(1, DiagnosticSeverity::Warning),
+        diagnostic(0, DiagnosticSeverity::Hint),
+        diagnostic(1, DiagnosticSeverity::Error),
+        diagnostic(0, DiagnosticSeverity::Hint),
+    ];
+    let diagnostics: Vec<_> = diagnostics.iter().collect();
+    let lines = vec!["a".to_owned(), "b".to_owned()];
+
+    let summary = |signs: Vec<Sign>| -> Vec<_> {
+        signs
+            .into_iter()
+            .map(|sign| (sign.line, sign.name(), sign.text))
+            .collect()
+    };
+    assert_eq!(
+        summary(get_signs(&diagnostics, &lines, false)),
+        vec![
+            (1, "LanguageClientHint".to_owned(), "a".to_owned()),
+            (2, "LanguageClientError".to_owned(), "b".to_owned()),
+        ]
+    );
     assert_eq!(
-        get_command_add_sign(&sign, ""),
-        "sign place 75000 line=1 name=LanguageClientError file="
+        summary(get_signs(&diagnostics, &lines, true)),
+        vec![
+            (1, "LanguageClientHint".to_owned(), "a".to_owned()),
+            (2, "LanguageClientErrorCombined".to_owned(), "b".to_owned()),
+        ]
     );
+}
+
+pub const SIGN_GROUP: &str = "LanguageClient";
+
+/// Signs are placed in group `SIGN_GROUP` with priority if sign groups are supported.
+fn get_command_add_sign(sign: &Sign, filename: &str, priority: Option<u64>) -> String {
+    match priority {
+        Some(priority) => format!(
+            "sign place {} group={} priority={} line={} name={} file={}",
+            sign.id,
+            SIGN_GROUP,
+            priority,
+            sign.line,
+            sign.name(),
+            filename
+        ),
+        None => format!(
+            "sign place {} line={} name={} file={}",
+            sign.id,
+            sign.line,
+            sign.name(),
+            filename
+        ),
+    }
+}
 
-    let sign = Sign::new(7, "".to_owned(), Some(DiagnosticSeverity::Error));
+#[test]
+fn test_get_command_add_sign() {
+    let mut sign = Sign::new(1, "".to_owned(), Some(DiagnosticSeverity::Error));
+    sign.id = 3;
     assert_eq!(
-        get_command_add_sign(&sign, ""),
-        "sign place 75024 line=7 name=LanguageClientError file="
+        get_command_add_sign(&sign, "", None),
+        "sign place 3 line=1 name=LanguageClientError file="
     );
 
-    let sign = Sign::new(7, "".to_owned(), Some(DiagnosticSeverity::Hint));
+    sign.combined = true;
     assert_eq!(
-        get_command_add_sign(&sign, ""),
-        "sign place 75027 line=7 name=LanguageClientHint file="
+        get_command_add_sign(&sign, "/tmp/a.rs", Some(10)),
+        "sign place 3 group=LanguageClient priority=10 line=1 name=LanguageClientErrorCombined file=/tmp/a.rs"
     );
 }
 
-fn get_command_delete_sign(sign: &Sign, filename: &str) -> String {
-    format!("sign unplace {} file={}", sign.id, filename)
+fn get_command_delete_sign(sign: &Sign, filename: &str, priority: Option<u64>) -> String {
+    match priority {
+        Some(_) => format!(
+            "sign unplace {} group={} file={}",
+            sign.id, SIGN_GROUP, filename
+        ),
+        None => format!("sign unplace {} file={}", sign.id, filename),
+    }
 }
 
 #[test]
-fn test_get_command_delete_sign() {}
+fn test_get_command_delete_sign() {
+    let mut sign = Sign::new(1, "".to_owned(), Some(DiagnosticSeverity::Error));
+    sign.id = 3;
+    assert_eq!(
+        get_command_delete_sign(&sign, "f", Some(10)),
+        "sign unplace 3 group=LanguageClient file=f"
+    );
+}
 
 use diff;
 
+/// Get signs placed and commands to update them. New signs get ids from `next_id`, which are
+/// never reused within a buffer.
 pub fn get_command_update_signs(
     signs_prev: &[Sign],
     signs: &[Sign],
     filename: &str,
+    priority: Option<u64>,
+    next_id: &mut u64,
 ) -> (Vec<Sign>, Vec<String>) {
-    // Sign id might become different due to lines shifting. Use sign's existing sign id to
+    // Signs might be on different lines due to lines shifting. Use sign's existing sign id to
     // track same sign.
     let mut signs_next = vec![];
 
@@ -274,11 +851,14 @@ pub fn get_command_update_signs(
     for comp in diff::slice(signs_prev, signs) {
         match comp {
             diff::Result::Left(sign) => {
-                cmds.push(get_command_delete_sign(sign, filename));
+                cmds.push(get_command_delete_sign(sign, filename, priority));
             }
             diff::Result::Right(sign) => {
-                cmds.push(get_command_add_sign(sign, filename));
-                signs_next.push(sign.clone());
+                let mut sign = sign.clone();
+                sign.id = *next_id;
+                *next_id += 1;
+                cmds.push(get_command_add_sign(&sign, filename, priority));
+                signs_next.push(sign);
             }
             diff::Result::Both(sign, _) => {
                 signs_next.push(sign.clone());
@@ -291,22 +871,42 @@ pub fn get_command_update_signs(
 
 #[test]
 fn test_get_command_update_signs() {
-    let signs_prev = vec![Sign::new(
+    let mut signs_prev = vec![Sign::new(
         1,
         "abcde".to_string(),
         Some(DiagnosticSeverity::Error),
     )];
+    signs_prev[0].id = 1;
     let signs = vec![Sign::new(
         3,
         "abcde".to_string(),
         Some(DiagnosticSeverity::Error),
     )];
-    let (signs_next, cmds) = get_command_update_signs(&signs_prev, &signs, "f");
+    let mut next_id = 2;
+    let (signs_next, cmds) =
+        get_command_update_signs(&signs_prev, &signs, "f", Some(10), &mut next_id);
     assert_eq!(
         serde_json::to_string(&signs_next).unwrap(),
-        "[{\"id\":75000,\"line\":1,\"text\":\"abcde\",\"severity\":1}]"
+        "[{\"id\":1,\"line\":1,\"text\":\"abcde\",\"severity\":1,\"combined\":false}]"
     );
     assert!(cmds.is_empty());
+
+    let signs = vec![Sign::new(
+        3,
+        "fg".to_string(),
+        Some(DiagnosticSeverity::Error),
+    )];
+    let (signs_next, cmds) =
+        get_command_update_signs(&signs_prev, &signs, "f", Some(10), &mut next_id);
+    assert_eq!(signs_next[0].id, 2);
+    assert_eq!(next_id, 3);
+    assert_eq!(
+        cmds,
+        vec![
+            "sign unplace 1 group=LanguageClient file=f",
+            "sign place 2 group=LanguageClient priority=10 line=3 name=LanguageClientError file=f",
+        ]
+    );
 }
 
 pub trait Combine {
@@ -479,6 +1079,214 @@ fn test_diff_value() {
     );
 }
 
+/// Get path of file relative to project root, with `/` separators so that files written with it,
+/// e.g., diagnostics baselines and exports, can be shared across platforms.
+pub fn get_relative_path(filename: &str, root: &str) -> String {
+    match diff_paths(Path::new(filename), Path::new(root)) {
+        Some(relpath) => relpath
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => filename.to_owned(),
+    }
+}
+
+#[test]
+fn test_get_relative_path() {
+    let root = Path::new("/tmp").join("project");
+    let filename = root.join("src").join("lib.rs");
+    assert_eq!(
+        get_relative_path(&filename.to_string_lossy(), &root.to_string_lossy()),
+        "src/lib.rs"
+    );
+}
+
+/// Get links to descriptions of diagnostic codes in `textDocument/publishDiagnostics` params, i.e.,
+/// `codeDescription`, which lsp-types doesn't support yet.
+pub fn get_code_descriptions(params: &Value) -> Vec<(String, String)> {
+    let diagnostics = match params.get("diagnostics").and_then(Value::as_array) {
+        Some(diagnostics) => diagnostics,
+        None => return vec![],
+    };
+
+    diagnostics
+        .iter()
+        .filter_map(|dn| {
+            let code = match dn.get("code")? {
+                Value::String(code) => code.clone(),
+                Value::Number(code) => code.to_string(),
+                _ => return None,
+            };
+            let href = dn.get("codeDescription")?.get("href")?.as_str()?;
+            Some((code, href.to_owned()))
+        })
+        .collect()
+}
+
+#[test]
+fn test_get_code_descriptions() {
+    let params = json!({
+        "uri": "file:///tmp/a.rs",
+        "diagnostics": [{
+            "message": "mismatched types",
+            "code": "E0308",
+            "codeDescription": {"href": "https://doc.rust-lang.org/error-index.html#E0308"},
+        }, {
+            "message": "unused variable",
+            "code": 1,
+        }, {
+            "message": "unneeded return",
+            "code": 2,
+            "codeDescription": {"href": "https://example.com/2"},
+        }],
+    });
+    assert_eq!(
+        get_code_descriptions(&params),
+        vec![
+            (
+                "E0308".to_owned(),
+                "https://doc.rust-lang.org/error-index.html#E0308".to_owned()
+            ),
+            ("2".to_owned(), "https://example.com/2".to_owned()),
+        ]
+    );
+}
+
+/// Get the result id and, for "full" reports, the diagnostics of a pulled diagnostic report.
+/// "unchanged" reports have no diagnostics, as the ones of the previous result still apply.
+pub fn parse_diagnostic_report(report: &Value) -> Fallible<(Option<String>, Option<Value>)> {
+    let result_id = report["resultId"].as_str().map(ToOwned::to_owned);
+    let items = match report["kind"].as_str() {
+        Some("full") => Some(report["items"].clone()),
+        Some("unchanged") => None,
+        _ => bail!("Invalid diagnostic report: {}", report),
+    };
+    Ok((result_id, items))
+}
+
+#[test]
+fn test_parse_diagnostic_report() {
+    let items = json!([{
+        "range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 1}},
+        "message": "unused variable",
+    }]);
+    assert_eq!(
+        parse_diagnostic_report(&json!({"kind": "full", "resultId": "1", "items": items})).unwrap(),
+        (Some("1".to_owned()), Some(items))
+    );
+    assert_eq!(
+        parse_diagnostic_report(&json!({"kind": "unchanged", "resultId": "2"})).unwrap(),
+        (Some("2".to_owned()), None)
+    );
+    assert_eq!(
+        parse_diagnostic_report(&json!({"kind": "full", "items": []})).unwrap(),
+        (None, Some(json!([])))
+    );
+    assert!(parse_diagnostic_report(&json!({"items": []})).is_err());
+}
+
+/// Message of a diagnostic as echoed for the line, e.g., "[Error][E0308]mismatched types".
+pub fn get_line_diagnostic(diagnostic: &Diagnostic) -> String {
+    let mut msg = String::new();
+    if let Some(severity) = diagnostic.severity {
+        msg += &format!("[{:?}]", severity);
+    }
+    if let Some(ref code) = diagnostic.code {
+        let s = code.to_string();
+        if !s.is_empty() {
+            msg += &format!("[{}]", s);
+        }
+    }
+    msg += &diagnostic.message;
+    msg
+}
+
+/// Find the diagnostic starting closest after (or before) position, or the first (or last) one
+/// without position.
+pub fn find_diagnostic(
+    diagnostics: &[Diagnostic],
+    position: Option<Position>,
+    forward: bool,
+) -> Option<Diagnostic> {
+    let candidates = diagnostics.iter().filter(|dn| match position {
+        Some(position) if forward => dn.range.start > position,
+        Some(position) => dn.range.start < position,
+        None => true,
+    });
+    let found = if forward {
+        candidates.min_by_key(|dn| dn.range.start)
+    } else {
+        candidates.max_by_key(|dn| dn.range.start)
+    };
+    found.cloned()
+}
+
+#[test]
+fn test_find_diagnostic() {
+    let diagnostic = |line, character, message: &str| Diagnostic {
+        range: Range::new(
+            Position::new(line, character),
+            Position::new(line, character + 1),
+        ),
+        message: message.to_owned(),
+        ..Diagnostic::default()
+    };
+    // Not sorted by position.
+    let diagnostics = vec![
+        diagnostic(1, 8, "b"),
+        diagnostic(1, 2, "a"),
+        diagnostic(5, 0, "c"),
+    ];
+    let message =
+        |position, forward| find_diagnostic(&diagnostics, position, forward).map(|dn| dn.message);
+
+    assert_eq!(
+        message(Some(Position::new(1, 2)), true),
+        Some("b".to_owned())
+    );
+    assert_eq!(
+        message(Some(Position::new(1, 8)), false),
+        Some("a".to_owned())
+    );
+    assert_eq!(message(Some(Position::new(5, 0)), true), None);
+    assert_eq!(message(Some(Position::new(0, 0)), false), None);
+    assert_eq!(message(None, true), Some("a".to_owned()));
+    assert_eq!(message(None, false), Some("c".to_owned()));
+}
+
+/// Whether a buffer name is the URI of a virtual document, i.e., of a document that is not a file
+/// on disk. Should be in sync with `LSP#filename()`.
+pub fn is_virtual_document(path: &str) -> bool {
+    match path.find(':') {
+        Some(idx) => {
+            let (scheme, rest) = path.split_at(idx);
+            scheme.len() > 1
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+                && rest.starts_with(":/")
+                && scheme != "file"
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn test_is_virtual_document() {
+    assert!(is_virtual_document(
+        "jdt://contents/rt.jar/java.util/List.class"
+    ));
+    assert!(is_virtual_document(
+        "csharp:/metadata/projects/a/System.String.cs"
+    ));
+    assert!(!is_virtual_document("file:///tmp/a.rs"));
+    assert!(!is_virtual_document("/tmp/a.rs"));
+    assert!(!is_virtual_document("C:/Users/a.rs"));
+    assert!(!is_virtual_document("C:\\Users\\a.rs"));
+}
+
 pub trait Canonicalize {
     fn canonicalize(&self) -> String;
 }
@@ -549,3 +1357,101 @@ pub fn decode_parameterLabel(
         }
     }
 }
+
+/// Flatten hierarchical document symbols into symbol information, in document order. Children
+/// get their parent's name as container name, and the symbol's selection range as location.
+pub fn flatten_document_symbols(
+    uri: &Url,
+    symbols: &[DocumentSymbol],
+    container_name: Option<&str>,
+) -> Vec<SymbolInformation> {
+    let mut flattened = vec![];
+    for sym in symbols {
+        flattened.push(SymbolInformation {
+            name: sym.name.clone(),
+            kind: sym.kind,
+            deprecated: sym.deprecated,
+            location: Location {
+                uri: uri.clone(),
+                range: sym.selection_range,
+            },
+            container_name: container_name.map(ToOwned::to_owned),
+        });
+        if let Some(ref children) = sym.children {
+            flattened.extend(flatten_document_symbols(uri, children, Some(&sym.name)));
+        }
+    }
+    flattened
+}
+
+#[test]
+fn test_flatten_document_symbols() {
+    let uri: Url = "file:///tmp/lib.rs".parse().unwrap();
+    let symbol = |name: &str, line: u64, children: Option<Vec<DocumentSymbol>>| DocumentSymbol {
+        name: name.to_owned(),
+        detail: None,
+        kind: SymbolKind::Function,
+        deprecated: None,
+        range: Range::new(Position::new(line, 0), Position::new(line + 1, 0)),
+        selection_range: Range::new(Position::new(line, 3), Position::new(line, 6)),
+        children,
+    };
+    let symbols = vec![
+        symbol("Foo", 0, Some(vec![symbol("new", 1, None)])),
+        symbol("bar", 5, None),
+    ];
+
+    let flattened = flatten_document_symbols(&uri, &symbols, None);
+    let names: Vec<_> = flattened
+        .iter()
+        .map(|sym| (sym.name.as_str(), sym.container_name.as_deref()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![("Foo", None), ("new", Some("Foo")), ("bar", None)]
+    );
+    assert_eq!(flattened[1].location.range.start, Position::new(1, 3));
+}
+
+/// Get locations of a goto response. Links locate their target by its selection range, e.g., the
+/// name of a function rather than its whole definition.
+pub fn get_locations(response: GotoDefinitionResponse) -> Vec<Location> {
+    match response {
+        GotoDefinitionResponse::Scalar(location) => vec![location],
+        GotoDefinitionResponse::Array(locations) => locations,
+        GotoDefinitionResponse::Link(links) => links
+            .into_iter()
+            .map(|link| Location {
+                uri: link.target_uri,
+                range: link.target_selection_range.unwrap_or(link.target_range),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_get_locations() {
+    let uri: Url = "file:///tmp/lib.rs".parse().unwrap();
+    let target_range = Range::new(Position::new(1, 0), Position::new(3, 1));
+    let target_selection_range = Range::new(Position::new(1, 3), Position::new(1, 6));
+    let response = GotoDefinitionResponse::Link(vec![
+        LocationLink {
+            origin_selection_range: None,
+            target_uri: uri.clone(),
+            target_range,
+            target_selection_range: Some(target_selection_range),
+        },
+        LocationLink {
+            origin_selection_range: None,
+            target_uri: uri.clone(),
+            target_range,
+            target_selection_range: None,
+        },
+    ]);
+
+    let ranges: Vec<_> = get_locations(response)
+        .into_iter()
+        .map(|location| location.range)
+        .collect();
+    assert_eq!(ranges, vec![target_selection_range, target_range]);
+}
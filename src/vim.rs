@@ -74,27 +74,7 @@ impl Vim {
         let path = path.as_ref().to_string_lossy();
 
         let goto = goto_cmd.as_deref().unwrap_or("edit");
-        self.rpcclient.notify("s:Edit", json!([goto, path]))?;
-
-        if path.starts_with("jdt://") {
-            self.command("setlocal buftype=nofile filetype=java noswapfile")?;
-
-            // TODO
-            // let result = self.java_classFileContents(&json!({
-            //     VimVar::LanguageId.to_key(): "java",
-            //     "uri": path,
-            // }))?;
-            // let content = match result {
-            //     Value::String(s) => s,
-            //     _ => bail!("Unexpected type: {:?}", result),
-            // };
-            // let lines: Vec<String> = content
-            //     .lines()
-            //     .map(std::string::ToString::to_string)
-            //     .collect();
-            // self.setline(1, &lines)?;
-        }
-        Ok(())
+        self.rpcclient.notify("s:Edit", json!([goto, path]))
     }
 
     pub fn setqflist(&self, list: &[QuickfixEntry], action: &str, title: &str) -> Fallible<()> {
@@ -114,6 +94,13 @@ impl Vim {
         Ok(())
     }
 
+    /// Update quickfix or location list with given title, without touching other lists.
+    pub fn update_list(&self, list: &[QuickfixEntry], title: &str, loclist: bool) -> Fallible<()> {
+        let parms = json!([list, title, loclist as u8]);
+        self.rpcclient.notify("s:UpdateList", parms)?;
+        Ok(())
+    }
+
     pub fn create_namespace(&self, name: &str) -> Fallible<i64> {
         self.rpcclient.call("nvim_create_namespace", [name])
     }
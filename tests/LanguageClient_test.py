@@ -160,6 +160,23 @@ def test_textDocument_references(nvim):
     assert nvim.current.window.cursor == [13, 16]
 
 
+def test_textDocument_references_after_peek(nvim):
+    nvim.command("edit! {}".format(PATH_INDEXJS))
+    time.sleep(1)
+    nvim.funcs.cursor(13, 19)
+    nvim.call("LanguageClient#textDocument_definition", {"peek": 1})
+    time.sleep(1)
+
+    assert nvim.current.window.cursor == [13, 18]
+    nvim.command("pclose")
+
+    nvim.funcs.cursor(7, 12)
+    nvim.funcs.LanguageClient_textDocument_references()
+    time.sleep(1)
+
+    assert len(nvim.funcs.getloclist(0)) == 2
+
+
 def test_textDocument_references_modified_buffer(nvim):
     nvim.command("edit! {}".format(PATH_INDEXJS))
     time.sleep(1)